@@ -0,0 +1,335 @@
+use axum::{
+    extract::{multipart::Field, DefaultBodyLimit, FromRequestParts, Multipart, Path, State},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    response::IntoResponse,
+    routing::{delete, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::env;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::{fs, io::AsyncWriteExt};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, ToSchema,
+};
+
+use crate::error::{AppError, AppJson, AppResult, ErrorResponse};
+use crate::models::{self, ModelBytes, ModelFormat, ModelResponse, ModelSource};
+use crate::AppState;
+
+/// Largest accepted model upload
+const MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 1024;
+
+/// Admin routes for loading and unloading models at runtime
+///
+/// Every route requires `Authorization: Bearer <ADMIN_TOKEN>`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/models/load", post(load))
+        .route(
+            "/admin/models/upload",
+            post(upload).layer(DefaultBodyLimit::max(MAX_UPLOAD_SIZE)),
+        )
+        .route("/admin/models/{ident}", delete(unload))
+        .route("/admin/rescan", post(rescan))
+}
+
+/// Adds the `admin_token` bearer scheme used by the admin routes to the OpenAPI document
+pub struct AdminToken;
+
+impl Modify for AdminToken {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+        }
+    }
+}
+
+/// Extractor that rejects requests without the admin token
+pub struct Admin;
+
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> AppResult<Self> {
+        let Some(token) = state.admin_token.as_deref() else {
+            return Err(AppError::new(
+                StatusCode::FORBIDDEN,
+                "admin_disabled",
+                "Admin endpoints are disabled, set ADMIN_TOKEN to enable them",
+            ));
+        };
+        let provided = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        // Compare every byte so that the time taken does not leak the token
+        let authorized = provided.is_some_and(|p| {
+            p.len() == token.len()
+                && p.bytes()
+                    .zip(token.bytes())
+                    .fold(0, |acc, (a, b)| acc | (a ^ b))
+                    == 0
+        });
+        if !authorized {
+            return Err(AppError::new(
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Missing or invalid admin token",
+            ));
+        }
+        Ok(Admin)
+    }
+}
+
+async fn model_response(state: &AppState, ident: &str) -> AppResult<ModelResponse> {
+    let info = state.tts_model.lock().await.model_info(ident)?;
+    let sources = state.sources.lock().await;
+    Ok(ModelResponse::new(info, sources.get(ident)))
+}
+
+fn model_exists(ident: &str) -> AppError {
+    AppError::new(
+        StatusCode::CONFLICT,
+        "model_exists",
+        format!("Model already loaded: {ident}"),
+    )
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoadRequest {
+    /// Ident of a model in `MODELS_PATH`
+    ident: String,
+}
+
+/// Load a model from the models directory
+#[utoipa::path(
+    post,
+    path = "/admin/models/load",
+    request_body = LoadRequest,
+    security(("admin_token" = [])),
+    responses(
+        (status = 201, description = "Return the loaded model", body = ModelResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 404, description = "No model files for the ident", body = ErrorResponse),
+        (status = 409, description = "Model already loaded", body = ErrorResponse),
+        (status = 422, description = "Invalid model files", body = ErrorResponse),
+    )
+)]
+pub async fn load(
+    _: Admin,
+    State(state): State<AppState>,
+    AppJson(req): AppJson<LoadRequest>,
+) -> AppResult<impl IntoResponse> {
+    if state.tts_model.lock().await.models().contains(&req.ident) {
+        return Err(model_exists(&req.ident));
+    }
+    let (ident, source) = models::discover(&state.models_path)
+        .await?
+        .into_iter()
+        .find(|(ident, _)| *ident == req.ident)
+        .ok_or_else(|| {
+            AppError::not_found(format!("No model files for {} in MODELS_PATH", req.ident))
+        })?;
+    let bytes = ModelBytes::read(&source).await?;
+    if !models::load(&state, &ident, bytes).await? {
+        return Err(model_exists(&ident));
+    }
+    state.sources.lock().await.insert(ident.clone(), source);
+    log::info!("Loaded: {ident}");
+    Ok((
+        StatusCode::CREATED,
+        Json(model_response(&state, &ident).await?),
+    ))
+}
+
+/// File in the temporary directory that is removed when dropped
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        Self(env::temp_dir().join(format!("sbv2_upload_{}_{n}", std::process::id())))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Write a multipart field to a temporary file chunk by chunk
+async fn save_field(mut field: Field<'_>) -> AppResult<TempFile> {
+    let temp = TempFile::new();
+    let mut file = fs::File::create(&temp.0).await?;
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| AppError::bad_request(e.body_text()))?
+    {
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(temp)
+}
+
+/// Multipart fields of an upload
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    ident: String,
+    /// A `.sbv2` or `.aivmx` file
+    #[schema(format = Binary, content_media_type = "application/octet-stream")]
+    file: Option<String>,
+    /// An onnx model, together with `style_vectors`
+    #[schema(format = Binary, content_media_type = "application/octet-stream")]
+    model: Option<String>,
+    /// The style vectors json of `model`
+    #[schema(format = Binary, content_media_type = "application/json")]
+    style_vectors: Option<String>,
+}
+
+/// Load a model from a multipart upload
+///
+/// Fields: `ident`, and either `file` (a `.sbv2` or `.aivmx` file) or `model` and
+/// `style_vectors` (an onnx model and its style vectors json).
+#[utoipa::path(
+    post,
+    path = "/admin/models/upload",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    security(("admin_token" = [])),
+    responses(
+        (status = 201, description = "Return the loaded model", body = ModelResponse),
+        (status = 400, description = "Malformed upload", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 409, description = "Model already loaded", body = ErrorResponse),
+        (status = 422, description = "Invalid model files", body = ErrorResponse),
+    )
+)]
+pub async fn upload(
+    _: Admin,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> AppResult<impl IntoResponse> {
+    let mut ident = None;
+    let mut file = None;
+    let mut model = None;
+    let mut style_vectors = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| AppError::bad_request(e.body_text()))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        match name.as_str() {
+            "ident" => {
+                ident = Some(
+                    field
+                        .text()
+                        .await
+                        .map_err(|e| AppError::bad_request(e.body_text()))?,
+                )
+            }
+            "file" => file = Some((file_name, save_field(field).await?)),
+            "model" => model = Some(save_field(field).await?),
+            "style_vectors" => style_vectors = Some(save_field(field).await?),
+            _ => {}
+        }
+    }
+    let ident = ident
+        .filter(|i| !i.is_empty())
+        .ok_or_else(|| AppError::bad_request("Missing ident"))?;
+    let (format, files) = match (file, model, style_vectors) {
+        (Some((name, file)), None, None) if name.ends_with(".sbv2") => {
+            (ModelFormat::Sbv2, vec![file])
+        }
+        (Some((name, file)), None, None) if name.ends_with(".aivmx") => {
+            (ModelFormat::Aivmx, vec![file])
+        }
+        (None, Some(model), Some(style_vectors)) => {
+            (ModelFormat::OnnxJson, vec![model, style_vectors])
+        }
+        _ => {
+            return Err(AppError::bad_request(
+                "Upload a .sbv2 or .aivmx file as `file`, or `model` and `style_vectors`",
+            ))
+        }
+    };
+    if state.tts_model.lock().await.models().contains(&ident) {
+        return Err(model_exists(&ident));
+    }
+    let bytes = ModelBytes::read(&ModelSource {
+        files: files
+            .iter()
+            .map(|f| f.0.to_string_lossy().to_string())
+            .collect(),
+        format,
+    })
+    .await?;
+    drop(files);
+    if !models::load(&state, &ident, bytes).await? {
+        return Err(model_exists(&ident));
+    }
+    let source = ModelSource {
+        files: vec![],
+        format,
+    };
+    state.sources.lock().await.insert(ident.clone(), source);
+    log::info!("Loaded uploaded model: {ident}");
+    Ok((
+        StatusCode::CREATED,
+        Json(model_response(&state, &ident).await?),
+    ))
+}
+
+/// Unload a model
+#[utoipa::path(
+    delete,
+    path = "/admin/models/{ident}",
+    params(("ident" = String, Path, description = "Model ident")),
+    security(("admin_token" = [])),
+    responses(
+        (status = 204, description = "Model unloaded"),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+    )
+)]
+pub async fn unload(
+    _: Admin,
+    State(state): State<AppState>,
+    Path(ident): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    if !state.tts_model.lock().await.unload(&ident) {
+        return Err(AppError::not_found(format!("Model not found: {ident}")));
+    }
+    state.sources.lock().await.remove(&ident);
+    log::info!("Unloaded: {ident}");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Load the models added to the models directory since the last scan
+#[utoipa::path(
+    post,
+    path = "/admin/rescan",
+    security(("admin_token" = [])),
+    responses(
+        (status = 200, description = "Return the idents of the newly loaded models", body = Vec<String>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Admin endpoints are disabled", body = ErrorResponse),
+    )
+)]
+pub async fn rescan(_: Admin, State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    Ok(Json(models::load_dir(&state).await?))
+}
@@ -1,17 +1,101 @@
 use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Query,
+    },
     http::StatusCode,
     response::{IntoResponse, Response},
+    Json,
 };
+use sbv2_core::error::Error;
+use serde::Serialize;
+use utoipa::ToSchema;
 
 pub type AppResult<T> = std::result::Result<T, AppError>;
 
-pub struct AppError(anyhow::Error);
+/// JSON body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Machine readable error code, e.g. `model_not_found`
+    code: &'static str,
+    message: String,
+    details: Option<String>,
+}
+
+pub struct AppError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: Option<String>,
+}
+
+impl AppError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn with_details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub(crate) fn from_core(err: &Error) -> Self {
+        match err {
+            Error::ModelNotFoundError(ident) => Self::new(
+                StatusCode::NOT_FOUND,
+                "model_not_found",
+                format!("Model not found: {ident}"),
+            ),
+            Error::ValueError(message) => {
+                Self::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid_value", message)
+            }
+            Error::TokenizerError(_) | Error::JPreprocessError(_) | Error::LinderaError(_) => {
+                Self::new(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "text_processing_failed",
+                    "The text could not be processed",
+                )
+                .with_details(err.to_string())
+            }
+            _ => Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+                "Something went wrong",
+            )
+            .with_details(err.to_string()),
+        }
+    }
+}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            log::error!("{}: {:?}", self.message, self.details);
+        }
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
+            self.status,
+            Json(ErrorResponse {
+                code: self.code,
+                message: self.message,
+                details: self.details,
+            }),
         )
             .into_response()
     }
@@ -22,6 +106,31 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        let err: anyhow::Error = err.into();
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return Self::from_core(err);
+        }
+        if let Some(rejection) = err.downcast_ref::<JsonRejection>() {
+            return Self::new(rejection.status(), "invalid_request", rejection.body_text());
+        }
+        if let Some(rejection) = err.downcast_ref::<QueryRejection>() {
+            return Self::new(rejection.status(), "invalid_request", rejection.body_text());
+        }
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Something went wrong",
+        )
+        .with_details(err.to_string())
     }
 }
+
+/// `Json` extractor that rejects with an `ErrorResponse`
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+/// `Query` extractor that rejects with an `ErrorResponse`
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(AppError))]
+pub struct AppQuery<T>(pub T);
@@ -1,36 +1,75 @@
 use axum::{
     extract::State,
     http::header::CONTENT_TYPE,
+    middleware,
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
-use sbv2_core::tts::{SynthesizeOptions, TTSModelHolder};
-use serde::Deserialize;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use sbv2_core::model::{Provider, SessionConfig, SessionOptions};
+use sbv2_core::postprocess::{Normalization, PostProcessOptions};
+use sbv2_core::tts::{JoinStrategy, SynthesisOutput, SynthesizeOptions, TTSModelHolder};
+use sbv2_core::tts_util::PauseOptions;
+use sbv2_core::{subtitle, tts_util, viseme};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
 use tokio::sync::Mutex;
 use utoipa::{OpenApi, ToSchema};
 use utoipa_scalar::{Scalar, Servable};
 
+mod admin;
 mod error;
-use crate::error::AppResult;
+mod metrics;
+mod models;
+mod openai;
+mod validation;
+mod voicevox;
+mod watch;
+mod ws;
+use crate::error::{AppJson, AppResult, ErrorResponse};
+use crate::models::{ModelFormat, ModelSource};
+use crate::validation::{check_range, check_voice, Limits};
 
 #[derive(OpenApi)]
-#[openapi(paths(models, synthesize), components(schemas(SynthesizeRequest)))]
-struct ApiDoc;
-
-#[utoipa::path(
-    get,
-    path = "/models",
-    responses(
-        (status = 200, description = "Return model list", body = Vec<String>),
-    )
+#[openapi(
+    paths(
+        models::models,
+        models::model,
+        synthesize,
+        synthesize_with_subtitles,
+        synthesize_with_timeline,
+        openai::speech,
+        metrics::metrics,
+        admin::load,
+        admin::upload,
+        admin::unload,
+        admin::rescan
+    ),
+    components(schemas(
+        SynthesizeRequest,
+        Join,
+        LengthUnit,
+        SynthesizeWithSubtitlesResponse,
+        Subtitle,
+        SynthesizeWithTimelineResponse,
+        Timing,
+        openai::SpeechRequest,
+        openai::ResponseFormat,
+        models::ModelResponse,
+        models::Style,
+        ModelFormat,
+        admin::LoadRequest,
+        admin::UploadForm,
+        ErrorResponse
+    )),
+    modifiers(&admin::AdminToken)
 )]
-async fn models(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
-    Ok(Json(state.tts_model.lock().await.models()))
-}
+struct ApiDoc;
 
 fn sdp_default() -> f32 {
     0.0
@@ -40,6 +79,10 @@ fn length_default() -> f32 {
     1.0
 }
 
+fn scale_default() -> f32 {
+    1.0
+}
+
 fn style_id_default() -> i32 {
     0
 }
@@ -60,6 +103,179 @@ struct SynthesizeRequest {
     style_id: i32,
     #[serde(default = "speaker_id_default")]
     speaker_id: i64,
+    /// Gain applied to the audio
+    #[serde(default = "scale_default")]
+    volume_scale: f32,
+    /// Pitch shift in octaves
+    #[serde(default)]
+    pitch_scale: f32,
+    /// Scale of the pitch deviation from the mean
+    #[serde(default = "scale_default")]
+    intonation_scale: f32,
+    /// Normalize to this integrated loudness in LUFS
+    normalize_lufs: Option<f32>,
+    /// Normalize to this sample peak in dBFS
+    normalize_peak: Option<f32>,
+    /// Trim leading and trailing audio quieter than this level in dBFS
+    trim_silence: Option<f32>,
+    /// Fade-in length in seconds
+    #[serde(default)]
+    fade_in: f32,
+    /// Fade-out length in seconds
+    #[serde(default)]
+    fade_out: f32,
+    /// Scale the audio down if it would clip
+    #[serde(default)]
+    prevent_clipping: bool,
+    /// Pause after `。！？` in seconds
+    sentence_pause: Option<f32>,
+    /// Pause after `、` in seconds
+    clause_pause: Option<f32>,
+    /// Pause after a line break in seconds
+    paragraph_pause: Option<f32>,
+    /// Pause after `…` in seconds
+    marker_pause: Option<f32>,
+    /// Split at every `、` so that `clause_pause` applies to each of them
+    #[serde(default)]
+    split_clauses: bool,
+    /// Split sentences longer than this at `、` or whitespace, 80 by default
+    max_sentence_length: Option<usize>,
+    /// Unit of `max_sentence_length`
+    #[serde(default)]
+    sentence_length_unit: LengthUnit,
+    /// How sentences are joined together
+    #[serde(default)]
+    join: Join,
+    /// Crossfade or fade length in seconds used by `join`
+    #[serde(default = "join_duration_default")]
+    join_duration: f32,
+    /// Maximum number of sentences synthesized concurrently, capped by `PARALLEL_SENTENCES`
+    parallel_sentences: Option<usize>,
+}
+
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Join {
+    #[default]
+    Silence,
+    Crossfade,
+    FadedSilence,
+}
+
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum LengthUnit {
+    Chars,
+    #[default]
+    Tokens,
+    Morae,
+}
+
+fn join_duration_default() -> f32 {
+    0.05
+}
+
+impl SynthesizeRequest {
+    /// Reject requests outside the server limits or the model's styles and speakers
+    fn validate(&self, limits: &Limits, tts_model: &TTSModelHolder) -> AppResult<()> {
+        limits.check_text(&self.text)?;
+        limits.check_length_scale(self.length_scale)?;
+        check_range("sdp_ratio", self.sdp_ratio, 0.0, 1.0)?;
+        check_range("volume_scale", self.volume_scale, 0.0, 10.0)?;
+        check_range("pitch_scale", self.pitch_scale, -1.0, 1.0)?;
+        check_range("intonation_scale", self.intonation_scale, 0.0, 2.0)?;
+        check_range("fade_in", self.fade_in, 0.0, 10.0)?;
+        check_range("fade_out", self.fade_out, 0.0, 10.0)?;
+        check_range("join_duration", self.join_duration, 0.0, 1.0)?;
+        let optional = [
+            ("normalize_lufs", self.normalize_lufs, -70.0, 0.0),
+            ("normalize_peak", self.normalize_peak, -60.0, 0.0),
+            ("trim_silence", self.trim_silence, -120.0, 0.0),
+            ("sentence_pause", self.sentence_pause, 0.0, 10.0),
+            ("clause_pause", self.clause_pause, 0.0, 10.0),
+            ("paragraph_pause", self.paragraph_pause, 0.0, 10.0),
+            ("marker_pause", self.marker_pause, 0.0, 10.0),
+            (
+                "max_sentence_length",
+                self.max_sentence_length.map(|v| v as f32),
+                1.0,
+                limits.max_text_length as f32,
+            ),
+        ];
+        for (name, value, min, max) in optional {
+            if let Some(value) = value {
+                check_range(name, value, min, max)?;
+            }
+        }
+        check_voice(tts_model, &self.ident, self.style_id, self.speaker_id)
+    }
+
+    fn options(&self) -> SynthesizeOptions {
+        let default = PauseOptions::default();
+        let default_options = SynthesizeOptions::default();
+        SynthesizeOptions {
+            sdp_ratio: self.sdp_ratio,
+            length_scale: self.length_scale,
+            volume_scale: self.volume_scale,
+            pitch_scale: self.pitch_scale,
+            intonation_scale: self.intonation_scale,
+            post_process: PostProcessOptions {
+                normalize: match (self.normalize_lufs, self.normalize_peak) {
+                    (Some(lufs), _) => Some(Normalization::Loudness(lufs)),
+                    (None, Some(peak)) => Some(Normalization::Peak(peak)),
+                    (None, None) => None,
+                },
+                trim_silence: self.trim_silence,
+                fade_in: self.fade_in,
+                fade_out: self.fade_out,
+                prevent_clipping: self.prevent_clipping,
+            },
+            pauses: PauseOptions {
+                sentence: self.sentence_pause.unwrap_or(default.sentence),
+                clause: self.clause_pause.unwrap_or(default.clause),
+                paragraph: self.paragraph_pause.unwrap_or(default.paragraph),
+                marker: self.marker_pause.unwrap_or(default.marker),
+                split_clauses: self.split_clauses,
+            },
+            join: match self.join {
+                Join::Silence => JoinStrategy::Silence,
+                Join::Crossfade => JoinStrategy::Crossfade(self.join_duration),
+                Join::FadedSilence => JoinStrategy::FadedSilence(self.join_duration),
+            },
+            max_sentence_length: self
+                .max_sentence_length
+                .or(default_options.max_sentence_length),
+            sentence_length_unit: match self.sentence_length_unit {
+                LengthUnit::Chars => tts_util::LengthUnit::Chars,
+                LengthUnit::Tokens => tts_util::LengthUnit::Tokens,
+                LengthUnit::Morae => tts_util::LengthUnit::Morae,
+            },
+            parallel_sentences: self.parallel_sentences.unwrap_or(usize::MAX),
+            ..default_options
+        }
+    }
+}
+
+/// Validate and synthesize `req`, recording its metrics
+async fn synthesize_detailed(
+    state: &AppState,
+    req: &SynthesizeRequest,
+) -> AppResult<SynthesisOutput> {
+    let mut tts_model = metrics::lock(&state.tts_model).await;
+    let synthesis = metrics::Synthesis::start(&tts_model, &req.ident, &req.text);
+    let result = req.validate(&state.limits, &tts_model).and_then(|()| {
+        Ok(tts_model.easy_synthesize_detailed(
+            &req.ident,
+            &req.text,
+            req.style_id,
+            req.speaker_id,
+            req.options(),
+        )?)
+    });
+    synthesis.finish(&result, |output| {
+        output.audio.len() as f32 / tts_util::SAMPLE_RATE as f32
+    });
+    result
 }
 
 #[utoipa::path(
@@ -67,120 +283,242 @@ struct SynthesizeRequest {
     path = "/synthesize",
     request_body = SynthesizeRequest,
     responses(
-        (status = 200, description = "Return audio/wav", body = Vec<u8>, content_type = "audio/wav")
+        (status = 200, description = "Return audio/wav", body = Vec<u8>, content_type = "audio/wav"),
+        (status = 400, description = "Malformed request", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+        (status = 422, description = "Invalid parameters or text that cannot be processed", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
     )
 )]
 async fn synthesize(
     State(state): State<AppState>,
-    Json(SynthesizeRequest {
-        text,
-        ident,
-        sdp_ratio,
-        length_scale,
-        style_id,
-        speaker_id,
-    }): Json<SynthesizeRequest>,
+    AppJson(req): AppJson<SynthesizeRequest>,
 ) -> AppResult<impl IntoResponse> {
-    log::debug!("processing request: text={text}, ident={ident}, sdp_ratio={sdp_ratio}, length_scale={length_scale}");
-    let buffer = {
-        let mut tts_model = state.tts_model.lock().await;
-        tts_model.easy_synthesize(
-            &ident,
-            &text,
-            style_id,
-            speaker_id,
-            SynthesizeOptions {
-                sdp_ratio,
-                length_scale,
-                ..Default::default()
-            },
-        )?
-    };
+    log::debug!(
+        "processing request: text={}, ident={}, sdp_ratio={}, length_scale={}",
+        req.text,
+        req.ident,
+        req.sdp_ratio,
+        req.length_scale
+    );
+    let output = synthesize_detailed(&state, &req).await?;
+    let buffer = tts_util::array_to_vec(output.audio)?;
     Ok(([(CONTENT_TYPE, "audio/wav")], buffer))
 }
 
+#[derive(Serialize, ToSchema)]
+struct Subtitle {
+    start: f32,
+    end: f32,
+    text: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SynthesizeWithSubtitlesResponse {
+    /// Base64 encoded audio/wav
+    audio: String,
+    subtitles: Vec<Subtitle>,
+    srt: String,
+    vtt: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/synthesize_with_subtitles",
+    request_body = SynthesizeRequest,
+    responses(
+        (status = 200, description = "Return audio/wav with per-sentence subtitles", body = SynthesizeWithSubtitlesResponse),
+        (status = 400, description = "Malformed request", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+        (status = 422, description = "Invalid parameters or text that cannot be processed", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    )
+)]
+async fn synthesize_with_subtitles(
+    State(state): State<AppState>,
+    AppJson(req): AppJson<SynthesizeRequest>,
+) -> AppResult<impl IntoResponse> {
+    log::debug!(
+        "processing request: text={}, ident={}, sdp_ratio={}, length_scale={}",
+        req.text,
+        req.ident,
+        req.sdp_ratio,
+        req.length_scale
+    );
+    let output = synthesize_detailed(&state, &req).await?;
+    let buffer = tts_util::array_to_vec(output.audio)?;
+    let cues = output.subtitles;
+    Ok(Json(SynthesizeWithSubtitlesResponse {
+        audio: BASE64_STANDARD.encode(buffer),
+        srt: subtitle::to_srt(&cues),
+        vtt: subtitle::to_vtt(&cues),
+        subtitles: cues
+            .into_iter()
+            .map(|c| Subtitle {
+                start: c.start,
+                end: c.end,
+                text: c.text,
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Serialize, ToSchema)]
+struct Timing {
+    /// Phoneme or viseme (a/i/u/e/o/N/closed)
+    label: String,
+    start: f32,
+    end: f32,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SynthesizeWithTimelineResponse {
+    /// Base64 encoded audio/wav
+    audio: String,
+    /// Empty if the model does not expose durations
+    phonemes: Vec<Timing>,
+    visemes: Vec<Timing>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/synthesize_with_timeline",
+    request_body = SynthesizeRequest,
+    responses(
+        (status = 200, description = "Return audio/wav with phoneme timings and a viseme timeline", body = SynthesizeWithTimelineResponse),
+        (status = 400, description = "Malformed request", body = ErrorResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+        (status = 422, description = "Invalid parameters or text that cannot be processed", body = ErrorResponse),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    )
+)]
+async fn synthesize_with_timeline(
+    State(state): State<AppState>,
+    AppJson(req): AppJson<SynthesizeRequest>,
+) -> AppResult<impl IntoResponse> {
+    log::debug!(
+        "processing request: text={}, ident={}, sdp_ratio={}, length_scale={}",
+        req.text,
+        req.ident,
+        req.sdp_ratio,
+        req.length_scale
+    );
+    let output = synthesize_detailed(&state, &req).await?;
+    let visemes = viseme::viseme_timeline(&output.phonemes)
+        .into_iter()
+        .map(|v| Timing {
+            label: v.viseme.to_string(),
+            start: v.start,
+            end: v.end,
+        })
+        .collect();
+    Ok(Json(SynthesizeWithTimelineResponse {
+        audio: BASE64_STANDARD.encode(tts_util::array_to_vec(output.audio)?),
+        phonemes: output
+            .phonemes
+            .into_iter()
+            .map(|p| Timing {
+                label: p.phoneme,
+                start: p.start,
+                end: p.end,
+            })
+            .collect(),
+        visemes,
+    }))
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {key}: {e}")),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Parse `{prefix}_{name}`, falling back to `ONNX_{name}` which applies to every session
+fn session_env_parse<T: std::str::FromStr>(prefix: &str, name: &str) -> anyhow::Result<Option<T>>
+where
+    T::Err: std::fmt::Display,
+{
+    match env_parse(&format!("{prefix}_{name}"))? {
+        Some(value) => Ok(Some(value)),
+        None => env_parse(&format!("ONNX_{name}")),
+    }
+}
+
+fn session_options_from_env(prefix: &str) -> anyhow::Result<SessionOptions> {
+    let mut options = SessionOptions::default();
+    if let Some(threads) = env_parse(&format!("{prefix}_INTRA_THREADS"))? {
+        options.intra_threads = threads;
+    }
+    if let Some(threads) = env_parse(&format!("{prefix}_INTER_THREADS"))? {
+        options.inter_threads = threads;
+    }
+    if let Some(level) = session_env_parse(prefix, "OPTIMIZATION_LEVEL")? {
+        options.optimization_level = level;
+    }
+    if let Some(arena) = session_env_parse(prefix, "MEMORY_ARENA")? {
+        options.memory_arena = arena;
+    }
+    if let Some(deterministic) = session_env_parse(prefix, "DETERMINISTIC")? {
+        options.deterministic = deterministic;
+    }
+    if let Ok(providers) = env::var(format!("{prefix}_PROVIDERS")).or(env::var("ONNX_PROVIDERS")) {
+        options.providers = Provider::parse_list(&providers)?;
+    }
+    options.cache_dir = env::var("ONNX_CACHE_DIR").ok().map(Into::into);
+    Ok(options)
+}
+
+fn session_config_from_env() -> anyhow::Result<SessionConfig> {
+    Ok(SessionConfig {
+        bert: session_options_from_env("BERT")?,
+        vits: session_options_from_env("VITS")?,
+    })
+}
+
 #[derive(Clone)]
 struct AppState {
     tts_model: Arc<Mutex<TTSModelHolder>>,
+    sources: Arc<Mutex<HashMap<String, ModelSource>>>,
+    models_path: Arc<str>,
+    admin_token: Option<Arc<str>>,
+    limits: Arc<Limits>,
+    openai_voice: Arc<openai::DefaultVoice>,
 }
 
 impl AppState {
     pub async fn new() -> anyhow::Result<Self> {
-        let mut tts_model = TTSModelHolder::new(
+        let mut tts_model = TTSModelHolder::with_session_config(
             &fs::read(env::var("BERT_MODEL_PATH")?).await?,
             &fs::read(env::var("TOKENIZER_PATH")?).await?,
             env::var("HOLDER_MAX_LOADED_MODElS")
                 .ok()
                 .and_then(|x| x.parse().ok()),
+            session_config_from_env()?,
         )?;
-        let models = env::var("MODELS_PATH").unwrap_or("models".to_string());
-        let mut f = fs::read_dir(&models).await?;
-        let mut entries = vec![];
-        while let Ok(Some(e)) = f.next_entry().await {
-            let name = e.file_name().to_string_lossy().to_string();
-            if name.ends_with(".onnx") && name.starts_with("model_") {
-                let name_len = name.len();
-                let name = name.chars();
-                entries.push(
-                    name.collect::<Vec<_>>()[6..name_len - 5]
-                        .iter()
-                        .collect::<String>(),
-                );
-            } else if name.ends_with(".sbv2") {
-                let entry = &name[..name.len() - 5];
-                log::info!("Try loading: {entry}");
-                let sbv2_bytes = match fs::read(format!("{models}/{entry}.sbv2")).await {
-                    Ok(b) => b,
-                    Err(e) => {
-                        log::warn!("Error loading sbv2_bytes from file {entry}: {e}");
-                        continue;
-                    }
-                };
-                if let Err(e) = tts_model.load_sbv2file(entry, sbv2_bytes) {
-                    log::warn!("Error loading {entry}: {e}");
-                };
-                log::info!("Loaded: {entry}");
-            } else if name.ends_with(".aivmx") {
-                let entry = &name[..name.len() - 6];
-                log::info!("Try loading: {entry}");
-                let aivmx_bytes = match fs::read(format!("{models}/{entry}.aivmx")).await {
-                    Ok(b) => b,
-                    Err(e) => {
-                        log::warn!("Error loading aivmx bytes from file {entry}: {e}");
-                        continue;
-                    }
-                };
-                if let Err(e) = tts_model.load_aivmx(entry, aivmx_bytes) {
-                    log::error!("Error loading {entry}: {e}");
-                }
-                log::info!("Loaded: {entry}");
-            }
-        }
-        for entry in entries {
-            log::info!("Try loading: {entry}");
-            let style_vectors_bytes =
-                match fs::read(format!("{models}/style_vectors_{entry}.json")).await {
-                    Ok(b) => b,
-                    Err(e) => {
-                        log::warn!("Error loading style_vectors_bytes from file {entry}: {e}");
-                        continue;
-                    }
-                };
-            let vits2_bytes = match fs::read(format!("{models}/model_{entry}.onnx")).await {
-                Ok(b) => b,
-                Err(e) => {
-                    log::warn!("Error loading vits2_bytes from file {entry}: {e}");
-                    continue;
-                }
-            };
-            if let Err(e) = tts_model.load(&entry, style_vectors_bytes, vits2_bytes) {
-                log::warn!("Error loading {entry}: {e}");
-            };
-            log::info!("Loaded: {entry}");
-        }
-        Ok(Self {
+        tts_model.set_warmup(env_parse("WARMUP")?.unwrap_or(false));
+        tts_model.set_parallel_sentences(env_parse("PARALLEL_SENTENCES")?.unwrap_or(1));
+        let state = Self {
             tts_model: Arc::new(Mutex::new(tts_model)),
-        })
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            models_path: env::var("MODELS_PATH")
+                .unwrap_or("models".to_string())
+                .into(),
+            admin_token: env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|t| !t.is_empty())
+                .map(Into::into),
+            limits: Arc::new(Limits::from_env()?),
+            openai_voice: Arc::new(openai::DefaultVoice::from_env()?),
+        };
+        models::load_dir(&state).await?;
+        Ok(state)
     }
 }
 
@@ -188,11 +526,32 @@ impl AppState {
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv_override().ok();
     env_logger::init();
-    let app = Router::new()
+    metrics::init();
+    let mut app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
         .route("/synthesize", post(synthesize))
-        .route("/models", get(models))
-        .with_state(AppState::new().await?)
+        .route(
+            "/synthesize_with_subtitles",
+            post(synthesize_with_subtitles),
+        )
+        .route("/synthesize_with_timeline", post(synthesize_with_timeline))
+        .route("/models", get(models::models))
+        .route("/models/{ident}", get(models::model))
+        .route("/metrics", get(metrics::metrics))
+        .merge(openai::router())
+        .merge(admin::router())
+        .merge(ws::router());
+    if env_parse("VOICEVOX_COMPAT")?.unwrap_or(false) {
+        app = app.merge(voicevox::router());
+    }
+    let app = app.route_layer(middleware::from_fn(metrics::track));
+    let state = AppState::new().await?;
+    if env_parse("WATCH_MODELS")?.unwrap_or(false) {
+        let interval = env_parse("WATCH_INTERVAL")?.unwrap_or(2);
+        watch::spawn(state.clone(), Duration::from_secs(interval)).await?;
+    }
+    let app = app
+        .with_state(state)
         .merge(Scalar::with_url("/docs", ApiDoc::openapi()));
     let addr = env::var("ADDR").unwrap_or("0.0.0.0:3000".to_string());
     let listener = tokio::net::TcpListener::bind(&addr).await?;
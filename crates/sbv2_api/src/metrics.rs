@@ -0,0 +1,241 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, histogram_opts, opts, Collector, CounterVec, HistogramVec, IntCounterVec,
+    IntGauge, Registry, TextEncoder,
+};
+use sbv2_core::metrics::Stage;
+use sbv2_core::tts::TTSModelHolder;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::error::{AppResult, ErrorResponse};
+use crate::AppState;
+
+struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    synthesis_requests: IntCounterVec,
+    text_length: HistogramVec,
+    audio_seconds: CounterVec,
+    real_time_factor: HistogramVec,
+    stage_duration: HistogramVec,
+    registered_models: IntGauge,
+    loaded_models: IntGauge,
+    queue_depth: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let metrics = Self {
+            registry: Registry::new(),
+            http_requests: IntCounterVec::new(
+                opts!(
+                    "sbv2_http_requests_total",
+                    "HTTP requests by route and status"
+                ),
+                &["path", "status"],
+            )?,
+            http_duration: HistogramVec::new(
+                histogram_opts!(
+                    "sbv2_http_request_duration_seconds",
+                    "Time to respond to HTTP requests, including the wait for the model"
+                ),
+                &["path"],
+            )?,
+            synthesis_requests: IntCounterVec::new(
+                opts!(
+                    "sbv2_synthesis_requests_total",
+                    "Synthesis requests by model and status"
+                ),
+                &["model", "status"],
+            )?,
+            text_length: HistogramVec::new(
+                histogram_opts!(
+                    "sbv2_text_length_characters",
+                    "Length of the synthesized text",
+                    exponential_buckets(10.0, 2.0, 10)?
+                ),
+                &["model"],
+            )?,
+            audio_seconds: CounterVec::new(
+                opts!("sbv2_audio_seconds_total", "Seconds of audio produced"),
+                &["model"],
+            )?,
+            real_time_factor: HistogramVec::new(
+                histogram_opts!(
+                    "sbv2_real_time_factor",
+                    "Synthesis time divided by the length of the produced audio",
+                    vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]
+                ),
+                &["model"],
+            )?,
+            stage_duration: HistogramVec::new(
+                histogram_opts!(
+                    "sbv2_stage_duration_seconds",
+                    "Time spent in each stage of the synthesis"
+                ),
+                &["stage"],
+            )?,
+            registered_models: IntGauge::new(
+                "sbv2_registered_models",
+                "Models known to the server",
+            )?,
+            loaded_models: IntGauge::new("sbv2_loaded_models", "Models currently in memory")?,
+            queue_depth: IntGauge::new(
+                "sbv2_queue_depth",
+                "Requests waiting for the model to be free",
+            )?,
+        };
+        let collectors: Vec<Box<dyn Collector>> = vec![
+            Box::new(metrics.http_requests.clone()),
+            Box::new(metrics.http_duration.clone()),
+            Box::new(metrics.synthesis_requests.clone()),
+            Box::new(metrics.text_length.clone()),
+            Box::new(metrics.audio_seconds.clone()),
+            Box::new(metrics.real_time_factor.clone()),
+            Box::new(metrics.stage_duration.clone()),
+            Box::new(metrics.registered_models.clone()),
+            Box::new(metrics.loaded_models.clone()),
+            Box::new(metrics.queue_depth.clone()),
+        ];
+        for collector in collectors {
+            metrics.registry.register(collector)?;
+        }
+        Ok(metrics)
+    }
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| Metrics::new().expect("metric definitions are valid"));
+
+fn observe_stage(stage: Stage, duration: Duration) {
+    METRICS
+        .stage_duration
+        .with_label_values(&[stage.as_str()])
+        .observe(duration.as_secs_f64());
+}
+
+/// Start collecting the stage timings of `sbv2_core`
+pub fn init() {
+    Lazy::force(&METRICS);
+    sbv2_core::metrics::set_stage_observer(observe_stage);
+}
+
+/// Middleware counting requests by route and status
+///
+/// Must be added with `route_layer` so that the matched route is known.
+pub async fn track(request: Request, next: Next) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    METRICS
+        .http_requests
+        .with_label_values(&[path.as_str(), response.status().as_str()])
+        .inc();
+    METRICS
+        .http_duration
+        .with_label_values(&[path.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Decrements the queue depth when dropped, also if the waiting request is cancelled
+struct Queued;
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        METRICS.queue_depth.dec();
+    }
+}
+
+/// Lock the model, counting the wait in the queue depth
+pub async fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    METRICS.queue_depth.inc();
+    let _queued = Queued;
+    mutex.lock().await
+}
+
+/// Per-model metrics of one synthesis, recorded by `finish`
+pub struct Synthesis {
+    model: String,
+    start: Instant,
+}
+
+impl Synthesis {
+    /// Start timing a synthesis of `text`, once the model lock is held
+    ///
+    /// Idents that are not loaded are recorded as `unknown`, so that requests for made up
+    /// models cannot create new series.
+    pub fn start(tts_model: &TTSModelHolder, ident: &str, text: &str) -> Self {
+        let model = if tts_model.models().iter().any(|m| m == ident) {
+            ident.to_string()
+        } else {
+            "unknown".to_string()
+        };
+        METRICS
+            .text_length
+            .with_label_values(&[model.as_str()])
+            .observe(text.chars().count() as f64);
+        Self {
+            model,
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the status of the synthesis, and the audio length and real-time factor if it succeeded
+    pub fn finish<T>(self, result: &AppResult<T>, audio_seconds: impl FnOnce(&T) -> f32) {
+        let status = match result {
+            Ok(output) => {
+                let seconds = audio_seconds(output) as f64;
+                METRICS
+                    .audio_seconds
+                    .with_label_values(&[self.model.as_str()])
+                    .inc_by(seconds);
+                if seconds > 0.0 {
+                    METRICS
+                        .real_time_factor
+                        .with_label_values(&[self.model.as_str()])
+                        .observe(self.start.elapsed().as_secs_f64() / seconds);
+                }
+                StatusCode::OK
+            }
+            Err(e) => e.status(),
+        };
+        METRICS
+            .synthesis_requests
+            .with_label_values(&[self.model.as_str(), status.as_str()])
+            .inc();
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Return metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 500, description = "Internal error", body = ErrorResponse),
+    )
+)]
+pub async fn metrics(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    // Keep the last values instead of waiting for a synthesis to finish
+    if let Ok(tts_model) = state.tts_model.try_lock() {
+        let infos = tts_model.models_info();
+        METRICS.registered_models.set(infos.len() as i64);
+        METRICS
+            .loaded_models
+            .set(infos.iter().filter(|i| i.loaded).count() as i64);
+    }
+    let mut buffer = String::new();
+    TextEncoder::new().encode_utf8(&METRICS.registry.gather(), &mut buffer)?;
+    Ok(([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], buffer))
+}
@@ -0,0 +1,245 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use sbv2_core::tts::{ModelInfo, ModelLoader, TTSModel};
+use serde::Serialize;
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+use utoipa::ToSchema;
+
+use crate::error::{AppResult, ErrorResponse};
+use crate::AppState;
+
+/// File format a model was loaded from
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFormat {
+    #[serde(rename = "sbv2")]
+    Sbv2,
+    #[serde(rename = "aivmx")]
+    Aivmx,
+    #[serde(rename = "onnx+json")]
+    OnnxJson,
+}
+
+/// Where a model was loaded from
+#[derive(Clone, Debug)]
+pub struct ModelSource {
+    pub files: Vec<String>,
+    pub format: ModelFormat,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Style {
+    id: i32,
+    name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ModelResponse {
+    ident: String,
+    /// Files the model was loaded from, empty if unknown
+    source_files: Vec<String>,
+    format: Option<ModelFormat>,
+    styles: Vec<Style>,
+    /// `null` if the model does not record it
+    speaker_count: Option<usize>,
+    sample_rate: u32,
+    /// Whether the model is currently in memory
+    loaded: bool,
+    /// Unix time in seconds of the last synthesis
+    last_used: Option<u64>,
+}
+
+impl ModelResponse {
+    pub fn new(info: ModelInfo, source: Option<&ModelSource>) -> Self {
+        ModelResponse {
+            source_files: source.map(|s| s.files.clone()).unwrap_or_default(),
+            format: source.map(|s| s.format),
+            styles: info
+                .styles
+                .into_iter()
+                .enumerate()
+                .map(|(id, name)| Style {
+                    id: id as i32,
+                    name,
+                })
+                .collect(),
+            speaker_count: info.speaker_count,
+            sample_rate: info.sample_rate,
+            loaded: info.loaded,
+            last_used: info
+                .last_used
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            ident: info.ident,
+        }
+    }
+}
+
+/// Find the models in `dir` by file name, sorted by ident
+pub async fn discover(dir: &str) -> std::io::Result<Vec<(String, ModelSource)>> {
+    let mut f = fs::read_dir(dir).await?;
+    let mut found = vec![];
+    while let Ok(Some(e)) = f.next_entry().await {
+        let name = e.file_name().to_string_lossy().to_string();
+        let (ident, source) = if let Some(ident) = name
+            .strip_prefix("model_")
+            .and_then(|n| n.strip_suffix(".onnx"))
+        {
+            let source = ModelSource {
+                files: vec![
+                    format!("{dir}/{name}"),
+                    format!("{dir}/style_vectors_{ident}.json"),
+                ],
+                format: ModelFormat::OnnxJson,
+            };
+            (ident, source)
+        } else if let Some(ident) = name.strip_suffix(".sbv2") {
+            let source = ModelSource {
+                files: vec![format!("{dir}/{name}")],
+                format: ModelFormat::Sbv2,
+            };
+            (ident, source)
+        } else if let Some(ident) = name.strip_suffix(".aivmx") {
+            let source = ModelSource {
+                files: vec![format!("{dir}/{name}")],
+                format: ModelFormat::Aivmx,
+            };
+            (ident, source)
+        } else {
+            continue;
+        };
+        found.push((ident.to_string(), source));
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(found)
+}
+
+/// Model files read into memory
+pub enum ModelBytes {
+    Sbv2(Vec<u8>),
+    Aivmx(Vec<u8>),
+    OnnxJson {
+        model: Vec<u8>,
+        style_vectors: Vec<u8>,
+    },
+}
+
+impl ModelBytes {
+    pub async fn read(source: &ModelSource) -> std::io::Result<Self> {
+        Ok(match source.format {
+            ModelFormat::Sbv2 => ModelBytes::Sbv2(fs::read(&source.files[0]).await?),
+            ModelFormat::Aivmx => ModelBytes::Aivmx(fs::read(&source.files[0]).await?),
+            ModelFormat::OnnxJson => ModelBytes::OnnxJson {
+                model: fs::read(&source.files[0]).await?,
+                style_vectors: fs::read(&source.files[1]).await?,
+            },
+        })
+    }
+
+    pub fn format(&self) -> ModelFormat {
+        match self {
+            ModelBytes::Sbv2(_) => ModelFormat::Sbv2,
+            ModelBytes::Aivmx(_) => ModelFormat::Aivmx,
+            ModelBytes::OnnxJson { .. } => ModelFormat::OnnxJson,
+        }
+    }
+
+    /// Build the model, including its session, without touching the holder
+    pub fn build(self, loader: &ModelLoader, ident: &str) -> sbv2_core::error::Result<TTSModel> {
+        match self {
+            ModelBytes::Sbv2(bytes) => loader.load_sbv2file(ident, bytes),
+            ModelBytes::Aivmx(bytes) => loader.load_aivmx(ident, bytes),
+            ModelBytes::OnnxJson {
+                model,
+                style_vectors,
+            } => loader.load(ident, style_vectors, model),
+        }
+    }
+}
+
+/// Build a model on a blocking thread and add it to the holder
+///
+/// The model lock is only held to take the loader and to insert the model, so synthesis
+/// for other models keeps running while the session is created. Returns `false` if a model
+/// named `ident` was added in the meantime.
+pub async fn load(state: &AppState, ident: &str, bytes: ModelBytes) -> anyhow::Result<bool> {
+    let loader = state.tts_model.lock().await.loader();
+    let name = ident.to_string();
+    let model = tokio::task::spawn_blocking(move || bytes.build(&loader, &name)).await??;
+    Ok(state.tts_model.lock().await.insert(model))
+}
+
+/// Load every model in the models directory that is not loaded yet
+///
+/// Returns the idents of the newly loaded models. Models that fail to load are logged and
+/// skipped.
+pub async fn load_dir(state: &AppState) -> std::io::Result<Vec<String>> {
+    let mut loaded = vec![];
+    for (ident, source) in discover(&state.models_path).await? {
+        if state.tts_model.lock().await.models().contains(&ident) {
+            continue;
+        }
+        log::info!("Try loading: {ident}");
+        let bytes = match ModelBytes::read(&source).await {
+            Ok(b) => b,
+            Err(e) => {
+                log::warn!("Error reading model files of {ident}: {e}");
+                continue;
+            }
+        };
+        match load(state, &ident, bytes).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                log::warn!("Error loading {ident}: {e}");
+                continue;
+            }
+        }
+        state.sources.lock().await.insert(ident.clone(), source);
+        log::info!("Loaded: {ident}");
+        loaded.push(ident);
+    }
+    Ok(loaded)
+}
+
+#[utoipa::path(
+    get,
+    path = "/models",
+    responses(
+        (status = 200, description = "Return model list", body = Vec<ModelResponse>),
+    )
+)]
+pub async fn models(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let infos = state.tts_model.lock().await.models_info();
+    let sources = state.sources.lock().await;
+    Ok(Json(
+        infos
+            .into_iter()
+            .map(|info| {
+                let source = sources.get(&info.ident);
+                ModelResponse::new(info, source)
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/models/{ident}",
+    params(("ident" = String, Path, description = "Model ident")),
+    responses(
+        (status = 200, description = "Return a model", body = ModelResponse),
+        (status = 404, description = "Model not found", body = ErrorResponse),
+    )
+)]
+pub async fn model(
+    State(state): State<AppState>,
+    Path(ident): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let info = state.tts_model.lock().await.model_info(&ident)?;
+    let sources = state.sources.lock().await;
+    Ok(Json(ModelResponse::new(info, sources.get(&ident))))
+}
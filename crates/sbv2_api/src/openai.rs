@@ -0,0 +1,235 @@
+use axum::{
+    extract::State,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use mp3lame_encoder::{Builder, FlushNoGap, MonoPcm};
+use sbv2_core::metrics::{self as core_metrics, Stage};
+use sbv2_core::tts::{SynthesizeOptions, TTSModelHolder};
+use sbv2_core::{postprocess, tts_util};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::env_parse;
+use crate::error::{AppError, AppJson, AppResult};
+use crate::metrics;
+use crate::validation::check_range;
+use crate::AppState;
+
+/// Sample rate of `pcm` responses, as in the OpenAI API
+const PCM_SAMPLE_RATE: u32 = 24000;
+
+/// Voices of the OpenAI API, synthesized with `DefaultVoice`
+const STOCK_VOICES: [&str; 6] = ["alloy", "echo", "fable", "onyx", "nova", "shimmer"];
+
+/// Model and style used for the stock OpenAI voices such as `alloy`
+///
+/// # Fields
+/// - `model`: Model ident, `None` to use the requested `model` if loaded, else the first model
+/// - `style`: Style id or name
+#[derive(Debug, Clone)]
+pub struct DefaultVoice {
+    pub model: Option<String>,
+    pub style: String,
+}
+
+impl DefaultVoice {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            model: env_parse("OPENAI_DEFAULT_MODEL")?,
+            style: env_parse("OPENAI_DEFAULT_STYLE")?.unwrap_or("0".to_string()),
+        })
+    }
+}
+
+/// Routes compatible with the OpenAI text-to-speech API
+pub fn router() -> Router<AppState> {
+    Router::new().route("/v1/audio/speech", post(speech))
+}
+
+/// Audio format of the response
+///
+/// `opus`, `aac` and `flac` are accepted for compatibility but not supported, requests
+/// using them fail with 400.
+#[derive(Deserialize, ToSchema, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Mp3,
+    Opus,
+    Aac,
+    Flac,
+    Wav,
+    Pcm,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SpeechRequest {
+    /// Model ident, used when `voice` is a style of it
+    model: String,
+    /// Text to synthesize
+    input: String,
+    /// `ident`, `ident:style` or a style of `model`, where a style is an id or a name.
+    /// The stock OpenAI voices (`alloy`, `echo`, `fable`, `onyx`, `nova`, `shimmer`) use
+    /// `OPENAI_DEFAULT_MODEL` and `OPENAI_DEFAULT_STYLE`.
+    voice: String,
+    #[serde(default)]
+    response_format: ResponseFormat,
+    /// Speed from 0.25 to 4.0, mapped to `length_scale`
+    #[serde(default = "speed_default")]
+    speed: f32,
+}
+
+fn speed_default() -> f32 {
+    1.0
+}
+
+/// Resolve `voice` and `model` to a model ident and style id
+///
+/// Stock OpenAI voices that are not the name of a model or style fall back to `default`.
+fn resolve_voice(
+    tts_model: &TTSModelHolder,
+    model: &str,
+    voice: &str,
+    default: &DefaultVoice,
+) -> Option<(String, i32)> {
+    resolve_own_voice(tts_model, model, voice).or_else(|| {
+        if !STOCK_VOICES.contains(&voice) {
+            return None;
+        }
+        let models = tts_model.models();
+        let ident = default
+            .model
+            .clone()
+            .or_else(|| models.iter().find(|m| *m == model).cloned())
+            .or_else(|| models.first().cloned())?;
+        resolve_own_voice(tts_model, model, &format!("{ident}:{}", default.style))
+    })
+}
+
+/// Resolve a voice that names a loaded model or style
+fn resolve_own_voice(
+    tts_model: &TTSModelHolder,
+    model: &str,
+    voice: &str,
+) -> Option<(String, i32)> {
+    let models = tts_model.models();
+    let (ident, style) = match voice.split_once(':') {
+        Some((ident, style)) => (ident, style),
+        None if models.iter().any(|m| m == voice) => (voice, "0"),
+        None => (model, voice),
+    };
+    if !models.iter().any(|m| m == ident) {
+        return None;
+    }
+    let styles = tts_model.model_info(ident).ok()?.styles;
+    let style_id = match style.parse::<i32>() {
+        Ok(id) if id >= 0 && (id as usize) < styles.len() => id,
+        _ => styles.iter().position(|name| name == style)? as i32,
+    };
+    Some((ident.to_string(), style_id))
+}
+
+fn encode_mp3(samples: &[f32], sample_rate: u32) -> anyhow::Result<Vec<u8>> {
+    let mut builder = Builder::new().ok_or_else(|| anyhow::anyhow!("Failed to create LAME"))?;
+    builder
+        .set_num_channels(1)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    builder
+        .set_sample_rate(sample_rate)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    builder
+        .set_brate(mp3lame_encoder::Bitrate::Kbps128)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    let mut encoder = builder.build().map_err(|e| anyhow::anyhow!("{e}"))?;
+    let pcm: Vec<i16> = samples
+        .iter()
+        .map(|x| (x.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect();
+    let mut buffer = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(pcm.len()));
+    encoder
+        .encode_to_vec(MonoPcm(&pcm), &mut buffer)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    encoder
+        .flush_to_vec::<FlushNoGap>(&mut buffer)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+    Ok(buffer)
+}
+
+fn encode_pcm(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    postprocess::resample(samples, sample_rate, PCM_SAMPLE_RATE)
+        .iter()
+        .flat_map(|x| ((x.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/audio/speech",
+    request_body = SpeechRequest,
+    responses(
+        (status = 200, description = "Return audio in the requested format", body = Vec<u8>),
+        (status = 400, description = "Unknown voice, or an unsupported response format (opus, aac or flac)", body = crate::error::ErrorResponse),
+        (status = 422, description = "Invalid parameters or text that cannot be processed", body = crate::error::ErrorResponse),
+        (status = 500, description = "Internal error", body = crate::error::ErrorResponse),
+    )
+)]
+pub async fn speech(
+    State(state): State<AppState>,
+    AppJson(req): AppJson<SpeechRequest>,
+) -> AppResult<Response> {
+    log::debug!(
+        "processing OpenAI request: input={}, model={}, voice={}, speed={}",
+        req.input,
+        req.model,
+        req.voice,
+        req.speed
+    );
+    state.limits.check_text(&req.input)?;
+    check_range("speed", req.speed, 0.25, 4.0)?;
+    let options = SynthesizeOptions {
+        length_scale: 1.0 / req.speed,
+        ..Default::default()
+    };
+    let (samples, sample_rate) = {
+        let mut tts_model = metrics::lock(&state.tts_model).await;
+        let Some((ident, style_id)) =
+            resolve_voice(&tts_model, &req.model, &req.voice, &state.openai_voice)
+        else {
+            return Err(AppError::bad_request(format!(
+                "Unknown voice: {}",
+                req.voice
+            )));
+        };
+        let synthesis = metrics::Synthesis::start(&tts_model, &ident, &req.input);
+        let result = tts_model
+            .synthesize_samples(&ident, &req.input, style_id, 0, options)
+            .map_err(AppError::from);
+        synthesis.finish(&result, |(samples, sample_rate)| {
+            samples.len() as f32 / *sample_rate as f32
+        });
+        result?
+    };
+    let (content_type, buffer) = match req.response_format {
+        ResponseFormat::Mp3 => (
+            "audio/mpeg",
+            core_metrics::time(Stage::Encoding, || encode_mp3(&samples, sample_rate))?,
+        ),
+        ResponseFormat::Wav => (
+            "audio/wav",
+            tts_util::samples_to_wav(&samples, sample_rate, 1)?,
+        ),
+        ResponseFormat::Pcm => (
+            "audio/pcm",
+            core_metrics::time(Stage::Encoding, || encode_pcm(&samples, sample_rate)),
+        ),
+        ResponseFormat::Opus | ResponseFormat::Aac | ResponseFormat::Flac => {
+            return Err(AppError::bad_request(
+                "Unsupported response_format, use mp3, wav or pcm",
+            ));
+        }
+    };
+    Ok(([(CONTENT_TYPE, content_type)], buffer).into_response())
+}
@@ -0,0 +1,97 @@
+use axum::http::StatusCode;
+use sbv2_core::tts::TTSModelHolder;
+
+use crate::env_parse;
+use crate::error::{AppError, AppResult};
+
+/// Server-side limits applied to synthesis requests
+///
+/// # Fields
+/// - `max_text_length`: Maximum number of characters per request
+/// - `min_length_scale`: Smallest accepted `length_scale`
+/// - `max_length_scale`: Largest accepted `length_scale`
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_text_length: usize,
+    pub min_length_scale: f32,
+    pub max_length_scale: f32,
+}
+
+impl Limits {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            max_text_length: env_parse("MAX_TEXT_LENGTH")?.unwrap_or(1000),
+            min_length_scale: env_parse("MIN_LENGTH_SCALE")?.unwrap_or(0.1),
+            max_length_scale: env_parse("MAX_LENGTH_SCALE")?.unwrap_or(10.0),
+        })
+    }
+
+    pub fn check_text(&self, text: &str) -> AppResult<()> {
+        let length = text.chars().count();
+        if length > self.max_text_length {
+            return Err(AppError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "text_too_long",
+                format!(
+                    "Text is {length} characters long, the limit is {}",
+                    self.max_text_length
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn check_length_scale(&self, length_scale: f32) -> AppResult<()> {
+        check_range(
+            "length_scale",
+            length_scale,
+            self.min_length_scale,
+            self.max_length_scale,
+        )
+    }
+}
+
+/// Reject values outside `min..=max`, including NaN
+pub fn check_range(name: &str, value: f32, min: f32, max: f32) -> AppResult<()> {
+    if !(min..=max).contains(&value) {
+        return Err(invalid_parameter(format!(
+            "{name} must be between {min} and {max}, got {value}"
+        )));
+    }
+    Ok(())
+}
+
+/// Check that the model exists and has the given style and speaker
+pub fn check_voice(
+    tts_model: &TTSModelHolder,
+    ident: &str,
+    style_id: i32,
+    speaker_id: i64,
+) -> AppResult<()> {
+    let style_count = tts_model.style_count(ident)?;
+    if style_id < 0 || style_id as usize >= style_count {
+        return Err(invalid_parameter(format!(
+            "style_id must be between 0 and {}, got {style_id}",
+            style_count.saturating_sub(1)
+        )));
+    }
+    let speaker_count = tts_model.speaker_count(ident)?;
+    if speaker_id < 0 || speaker_count.is_some_and(|n| speaker_id as usize >= n) {
+        return Err(invalid_parameter(match speaker_count {
+            Some(n) => format!(
+                "speaker_id must be between 0 and {}, got {speaker_id}",
+                n.saturating_sub(1)
+            ),
+            None => format!("speaker_id must not be negative, got {speaker_id}"),
+        }));
+    }
+    Ok(())
+}
+
+fn invalid_parameter(message: String) -> AppError {
+    AppError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "invalid_parameter",
+        message,
+    )
+}
@@ -0,0 +1,504 @@
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use sbv2_core::jtalk;
+use sbv2_core::tts::SynthesizeOptions;
+use sbv2_core::tts_util::{self, PauseOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::{AppError, AppJson, AppQuery, AppResult};
+use crate::metrics;
+use crate::validation::{check_range, Limits};
+use crate::AppState;
+
+/// Accepted `outputSamplingRate` range
+const MIN_SAMPLING_RATE: u32 = 8000;
+const MAX_SAMPLING_RATE: u32 = 48000;
+
+/// VOICEVOX style ids are `speaker number * STYLE_ID_STRIDE + style id`
+const STYLE_ID_STRIDE: u32 = 100;
+
+/// Speaker numbers are taken from a hash of the model name modulo this
+const SPEAKER_NUMBERS: u64 = 100_000;
+
+/// Routes compatible with the VOICEVOX engine API
+///
+/// Models become speakers and their styles become VOICEVOX styles. Speaker numbers are
+/// derived from the model name, so style ids stay the same when other models are added.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/version", get(version))
+        .route("/speakers", get(speakers))
+        .route("/audio_query", post(audio_query))
+        .route("/accent_phrases", post(accent_phrases))
+        .route("/synthesis", post(synthesis))
+        .route("/user_dict", get(user_dict))
+        .route(
+            "/user_dict_word",
+            post(user_dict_unsupported)
+                .put(user_dict_unsupported)
+                .delete(user_dict_unsupported),
+        )
+        .route(
+            "/user_dict_word/{word_uuid}",
+            post(user_dict_unsupported)
+                .put(user_dict_unsupported)
+                .delete(user_dict_unsupported),
+        )
+}
+
+#[derive(Serialize)]
+struct Style {
+    name: String,
+    id: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct SupportedFeatures {
+    permitted_synthesis_morphing: &'static str,
+}
+
+#[derive(Serialize)]
+struct Speaker {
+    name: String,
+    speaker_uuid: String,
+    styles: Vec<Style>,
+    version: &'static str,
+    supported_features: SupportedFeatures,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Mora {
+    text: String,
+    consonant: Option<String>,
+    consonant_length: Option<f32>,
+    vowel: String,
+    vowel_length: f32,
+    pitch: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct AccentPhrase {
+    moras: Vec<Mora>,
+    accent: usize,
+    pause_mora: Option<Mora>,
+    #[serde(default)]
+    is_interrogative: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AudioQuery {
+    #[serde(rename = "accent_phrases")]
+    accent_phrases: Vec<AccentPhrase>,
+    speed_scale: f32,
+    pitch_scale: f32,
+    intonation_scale: f32,
+    volume_scale: f32,
+    pre_phoneme_length: f32,
+    post_phoneme_length: f32,
+    #[serde(default)]
+    pause_length: Option<f32>,
+    #[serde(default = "pause_length_scale_default")]
+    pause_length_scale: f32,
+    output_sampling_rate: u32,
+    output_stereo: bool,
+    /// AquesTalk-style reading of `accent_phrases`, ignored on input as in VOICEVOX
+    #[serde(default)]
+    kana: Option<String>,
+    /// Source text of the query, an extension of this server
+    ///
+    /// Used for synthesis while `accent_phrases` still read the same, so that the
+    /// models keep the context of the kanji.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sbv2_text: Option<String>,
+}
+
+fn pause_length_scale_default() -> f32 {
+    1.0
+}
+
+impl AudioQuery {
+    fn pauses(&self) -> PauseOptions {
+        let default = PauseOptions::default();
+        let scale = self.pause_length_scale;
+        PauseOptions {
+            sentence: self.pause_length.unwrap_or(default.sentence) * scale,
+            clause: self.pause_length.unwrap_or(default.clause) * scale,
+            paragraph: default.paragraph * scale,
+            marker: default.marker * scale,
+            ..default
+        }
+    }
+
+    /// Reject queries outside the bounds `SynthesizeRequest` accepts
+    fn validate(&self, limits: &Limits) -> AppResult<()> {
+        check_range(
+            "speedScale",
+            self.speed_scale,
+            1.0 / limits.max_length_scale,
+            1.0 / limits.min_length_scale,
+        )?;
+        check_range("pitchScale", self.pitch_scale, -1.0, 1.0)?;
+        check_range("intonationScale", self.intonation_scale, 0.0, 2.0)?;
+        check_range("volumeScale", self.volume_scale, 0.0, 10.0)?;
+        check_range("prePhonemeLength", self.pre_phoneme_length, 0.0, 10.0)?;
+        check_range("postPhonemeLength", self.post_phoneme_length, 0.0, 10.0)?;
+        check_range("pauseLengthScale", self.pause_length_scale, 0.0, 10.0)?;
+        let pauses = self.pauses();
+        for pause in [
+            pauses.sentence,
+            pauses.clause,
+            pauses.paragraph,
+            pauses.marker,
+        ] {
+            check_range("pauseLength * pauseLengthScale", pause, 0.0, 10.0)?;
+        }
+        check_range(
+            "outputSamplingRate",
+            self.output_sampling_rate as f32,
+            MIN_SAMPLING_RATE as f32,
+            MAX_SAMPLING_RATE as f32,
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TextQuery {
+    text: String,
+    speaker: u32,
+}
+
+#[derive(Deserialize)]
+struct SpeakerQuery {
+    speaker: u32,
+}
+
+/// FNV-1a hash of the model name
+fn hash(ident: &str, seed: u64) -> u64 {
+    ident.bytes().fold(seed, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Stable UUID-shaped id derived from the model name
+fn speaker_uuid(ident: &str) -> String {
+    let hex = format!(
+        "{:016x}{:016x}",
+        hash(ident, 0xcbf2_9ce4_8422_2325),
+        hash(ident, 0x8422_2325_cbf2_9ce4)
+    );
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Stable speaker number derived from the model name
+fn speaker_number(ident: &str) -> u32 {
+    (hash(ident, 0xcbf2_9ce4_8422_2325) % SPEAKER_NUMBERS) as u32
+}
+
+/// Models with their speaker numbers, in alphabetical order
+///
+/// A model whose number is already taken by a model earlier in the order is left out.
+fn numbered_models(state: &sbv2_core::tts::TTSModelHolder) -> Vec<(u32, String)> {
+    let mut models = state.models();
+    models.sort();
+    let mut numbered: Vec<(u32, String)> = vec![];
+    for ident in models {
+        let number = speaker_number(&ident);
+        if let Some((_, other)) = numbered.iter().find(|(n, _)| *n == number) {
+            log::warn!("{ident} has the same VOICEVOX speaker number as {other} and is skipped");
+            continue;
+        }
+        numbered.push((number, ident));
+    }
+    numbered
+}
+
+/// Resolve a VOICEVOX style id to a model and style id
+fn resolve_speaker(
+    tts_model: &sbv2_core::tts::TTSModelHolder,
+    speaker: u32,
+) -> AppResult<(String, i32)> {
+    let ident = numbered_models(tts_model)
+        .into_iter()
+        .find(|(number, _)| *number == speaker / STYLE_ID_STRIDE)
+        .map(|(_, ident)| ident)
+        .ok_or_else(|| AppError::not_found(format!("Unknown speaker: {speaker}")))?;
+    let style_id = speaker % STYLE_ID_STRIDE;
+    if style_id as usize >= tts_model.style_count(&ident)? {
+        return Err(AppError::not_found(format!("Unknown speaker: {speaker}")));
+    }
+    Ok((ident, style_id as i32))
+}
+
+/// Mora with placeholder lengths and a two-level pitch
+///
+/// The models do not predict per-mora pitch or lengths before synthesis, so these only
+/// sketch the accent for clients that display it. They are ignored by `/synthesis`.
+fn mora_from_core(mora: &jtalk::AccentMora, high: bool) -> Mora {
+    let voiced = mora.vowel.chars().all(|c| c.is_lowercase()) || mora.vowel == "N";
+    Mora {
+        text: mora.text.clone(),
+        consonant: mora.consonant.clone(),
+        consonant_length: mora.consonant.as_ref().map(|_| 0.05),
+        vowel: mora.vowel.clone(),
+        vowel_length: 0.1,
+        pitch: match (voiced, high) {
+            (false, _) => 0.0,
+            (true, true) => 5.8,
+            (true, false) => 5.4,
+        },
+    }
+}
+
+fn accent_phrase_from_core(phrase: &jtalk::AccentPhrase) -> AccentPhrase {
+    let moras = phrase
+        .moras
+        .iter()
+        .enumerate()
+        .map(|(i, mora)| {
+            // Tokyo-style pitch accent: low-high rise at the start, drop after the nucleus
+            let high = if phrase.accent == 1 {
+                i == 0
+            } else {
+                i != 0 && i < phrase.accent
+            };
+            mora_from_core(mora, high)
+        })
+        .collect();
+    AccentPhrase {
+        moras,
+        accent: phrase.accent,
+        pause_mora: phrase.pause.then(|| Mora {
+            text: "、".to_string(),
+            consonant: None,
+            consonant_length: None,
+            vowel: "pau".to_string(),
+            vowel_length: 0.3,
+            pitch: 0.0,
+        }),
+        is_interrogative: phrase.is_interrogative,
+    }
+}
+
+/// AquesTalk-style notation of accent phrases, as returned in VOICEVOX's `kana`
+///
+/// `'` follows the accent nucleus, `_` precedes unvoiced moras, `/` separates phrases and
+/// `、` marks pauses.
+fn aquestalk_kana(phrases: &[AccentPhrase]) -> String {
+    let mut kana = String::new();
+    for (i, phrase) in phrases.iter().enumerate() {
+        for (j, mora) in phrase.moras.iter().enumerate() {
+            if mora.vowel.chars().all(|c| c.is_uppercase()) && mora.vowel != "N" {
+                kana.push('_');
+            }
+            kana.push_str(&mora.text);
+            if j + 1 == phrase.accent {
+                kana.push('\'');
+            }
+        }
+        if phrase.is_interrogative {
+            kana.push('？');
+        }
+        if i + 1 < phrases.len() {
+            kana.push(if phrase.pause_mora.is_some() {
+                '、'
+            } else {
+                '/'
+            });
+        }
+    }
+    kana
+}
+
+/// Whether two lists of accent phrases read the same, ignoring pitch and lengths
+fn same_reading(a: &[AccentPhrase], b: &[AccentPhrase]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.accent == b.accent
+                && a.is_interrogative == b.is_interrogative
+                && a.pause_mora.is_some() == b.pause_mora.is_some()
+                && a.moras.len() == b.moras.len()
+                && a.moras.iter().zip(&b.moras).all(|(a, b)| a.text == b.text)
+        })
+}
+
+/// Text to synthesize for a query
+///
+/// The source text is used when `accent_phrases` were not edited, otherwise the moras.
+fn query_text(tts_model: &sbv2_core::tts::TTSModelHolder, query: &AudioQuery) -> AppResult<String> {
+    if let Some(text) = query.sbv2_text.as_ref().filter(|t| !t.is_empty()) {
+        let phrases: Vec<AccentPhrase> = tts_model
+            .accent_phrases(text)?
+            .iter()
+            .map(accent_phrase_from_core)
+            .collect();
+        if same_reading(&phrases, &query.accent_phrases) {
+            return Ok(text.clone());
+        }
+    }
+    let mut text = String::new();
+    for phrase in &query.accent_phrases {
+        for mora in &phrase.moras {
+            text.push_str(&mora.text);
+        }
+        if phrase.is_interrogative {
+            text.push('？');
+        } else if phrase.pause_mora.is_some() {
+            text.push('、');
+        }
+    }
+    Ok(text)
+}
+
+async fn version() -> impl IntoResponse {
+    Json(env!("CARGO_PKG_VERSION"))
+}
+
+async fn speakers(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let tts_model = state.tts_model.lock().await;
+    let speakers = numbered_models(&tts_model)
+        .into_iter()
+        .map(|(number, ident)| {
+            let styles = tts_model
+                .model_info(&ident)?
+                .styles
+                .into_iter()
+                .enumerate()
+                .map(|(style_id, name)| Style {
+                    name,
+                    id: number * STYLE_ID_STRIDE + style_id as u32,
+                    kind: "talk",
+                })
+                .collect();
+            Ok(Speaker {
+                speaker_uuid: speaker_uuid(&ident),
+                name: ident,
+                styles,
+                version: env!("CARGO_PKG_VERSION"),
+                supported_features: SupportedFeatures {
+                    permitted_synthesis_morphing: "NOTHING",
+                },
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Json(speakers))
+}
+
+async fn accent_phrases(
+    State(state): State<AppState>,
+    AppQuery(query): AppQuery<TextQuery>,
+) -> AppResult<impl IntoResponse> {
+    state.limits.check_text(&query.text)?;
+    let tts_model = state.tts_model.lock().await;
+    resolve_speaker(&tts_model, query.speaker)?;
+    let phrases = tts_model.accent_phrases(&query.text)?;
+    Ok(Json(
+        phrases
+            .iter()
+            .map(accent_phrase_from_core)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+async fn audio_query(
+    State(state): State<AppState>,
+    AppQuery(query): AppQuery<TextQuery>,
+) -> AppResult<impl IntoResponse> {
+    state.limits.check_text(&query.text)?;
+    let tts_model = state.tts_model.lock().await;
+    resolve_speaker(&tts_model, query.speaker)?;
+    let phrases: Vec<AccentPhrase> = tts_model
+        .accent_phrases(&query.text)?
+        .iter()
+        .map(accent_phrase_from_core)
+        .collect();
+    Ok(Json(AudioQuery {
+        kana: Some(aquestalk_kana(&phrases)),
+        accent_phrases: phrases,
+        speed_scale: 1.0,
+        pitch_scale: 0.0,
+        intonation_scale: 1.0,
+        volume_scale: 1.0,
+        pre_phoneme_length: 0.1,
+        post_phoneme_length: 0.1,
+        pause_length: None,
+        pause_length_scale: 1.0,
+        output_sampling_rate: tts_util::SAMPLE_RATE,
+        output_stereo: false,
+        sbv2_text: Some(query.text),
+    }))
+}
+
+async fn synthesis(
+    State(state): State<AppState>,
+    AppQuery(query): AppQuery<SpeakerQuery>,
+    AppJson(audio_query): AppJson<AudioQuery>,
+) -> AppResult<impl IntoResponse> {
+    audio_query.validate(&state.limits)?;
+    if let Some(text) = &audio_query.sbv2_text {
+        state.limits.check_text(text)?;
+    }
+    let options = SynthesizeOptions {
+        length_scale: 1.0 / audio_query.speed_scale,
+        pitch_scale: audio_query.pitch_scale,
+        intonation_scale: audio_query.intonation_scale,
+        volume_scale: audio_query.volume_scale,
+        pauses: audio_query.pauses(),
+        ..Default::default()
+    };
+    let samples = {
+        let mut tts_model = metrics::lock(&state.tts_model).await;
+        let (ident, style_id) = resolve_speaker(&tts_model, query.speaker)?;
+        let text = query_text(&tts_model, &audio_query)?;
+        state.limits.check_text(&text)?;
+        log::debug!(
+            "processing VOICEVOX request: text={text}, speaker={}",
+            query.speaker
+        );
+        let synthesis = metrics::Synthesis::start(&tts_model, &ident, &text);
+        let result = tts_model
+            .synthesize_samples(&ident, &text, style_id, 0, options)
+            .map_err(AppError::from);
+        synthesis.finish(&result, |(samples, sample_rate)| {
+            samples.len() as f32 / *sample_rate as f32
+        });
+        result?.0
+    };
+    let pre = (audio_query.pre_phoneme_length * tts_util::SAMPLE_RATE as f32) as usize;
+    let post = (audio_query.post_phoneme_length * tts_util::SAMPLE_RATE as f32) as usize;
+    let mut padded = vec![0.0; pre];
+    padded.extend(samples);
+    padded.resize(padded.len() + post, 0.0);
+    let sample_rate = audio_query.output_sampling_rate;
+    let resampled = sbv2_core::postprocess::resample(&padded, tts_util::SAMPLE_RATE, sample_rate);
+    let channels = if audio_query.output_stereo { 2 } else { 1 };
+    let buffer = tts_util::samples_to_wav(&resampled, sample_rate, channels)?;
+    Ok(([(CONTENT_TYPE, "audio/wav")], buffer))
+}
+
+async fn user_dict() -> impl IntoResponse {
+    Json(HashMap::<String, ()>::new())
+}
+
+async fn user_dict_unsupported() -> impl IntoResponse {
+    AppError::new(
+        StatusCode::NOT_IMPLEMENTED,
+        "not_implemented",
+        "User dictionaries are not supported",
+    )
+}
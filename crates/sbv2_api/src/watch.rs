@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+
+use crate::models::{self, ModelBytes, ModelSource};
+use crate::AppState;
+
+/// Size and modification time of each file of a model
+type Fingerprint = Vec<(u64, Option<SystemTime>)>;
+
+/// Fingerprint of a model's files, `None` if one of them is missing
+async fn fingerprint(source: &ModelSource) -> Option<Fingerprint> {
+    let mut fingerprint = vec![];
+    for file in &source.files {
+        let metadata = fs::metadata(file).await.ok()?;
+        fingerprint.push((metadata.len(), metadata.modified().ok()));
+    }
+    Some(fingerprint)
+}
+
+/// Watch the models directory by polling it every `interval`
+///
+/// New models are loaded, deleted ones unloaded and changed ones reloaded. Files must be
+/// unchanged for two polls in a row before they are read, so that copies in progress are
+/// not picked up. Polling is used instead of file system events because those are often
+/// missing on network volumes.
+pub async fn spawn(state: AppState, interval: Duration) -> std::io::Result<()> {
+    let mut known = HashMap::new();
+    let loaded = state.tts_model.lock().await.models();
+    for (ident, source) in models::discover(&state.models_path).await? {
+        if loaded.contains(&ident) {
+            if let Some(fingerprint) = fingerprint(&source).await {
+                known.insert(ident, fingerprint);
+            }
+        }
+    }
+    tokio::spawn(async move {
+        let mut pending = HashMap::new();
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = poll(&state, &mut known, &mut pending).await {
+                log::warn!("Error watching {}: {e}", state.models_path);
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn poll(
+    state: &AppState,
+    known: &mut HashMap<String, Fingerprint>,
+    pending: &mut HashMap<String, Fingerprint>,
+) -> std::io::Result<()> {
+    let mut current = HashMap::new();
+    for (ident, source) in models::discover(&state.models_path).await? {
+        if let Some(fingerprint) = fingerprint(&source).await {
+            current.insert(ident, (source, fingerprint));
+        }
+    }
+    let deleted: Vec<String> = known
+        .keys()
+        .filter(|ident| !current.contains_key(*ident))
+        .cloned()
+        .collect();
+    for ident in deleted {
+        known.remove(&ident);
+        if !from_models_path(state, &ident).await {
+            continue;
+        }
+        if state.tts_model.lock().await.unload(&ident) {
+            state.sources.lock().await.remove(&ident);
+            log::info!("Unloaded deleted model: {ident}");
+        }
+    }
+    for (ident, (source, fingerprint)) in current {
+        if known.get(&ident) == Some(&fingerprint) {
+            pending.remove(&ident);
+            continue;
+        }
+        if pending.get(&ident) != Some(&fingerprint) {
+            pending.insert(ident, fingerprint);
+            continue;
+        }
+        pending.remove(&ident);
+        // Remember failed files too, so they are retried only once they change again
+        known.insert(ident.clone(), fingerprint);
+        if !from_models_path(state, &ident).await {
+            log::warn!("Not replacing uploaded model {ident} with the one in the models directory");
+            continue;
+        }
+        match reload(state, &ident, source).await {
+            Ok(()) => log::info!("Loaded: {ident}"),
+            Err(e) => log::warn!("Error loading {ident}: {e}"),
+        }
+    }
+    Ok(())
+}
+
+/// Whether the model is either not loaded or was loaded from the models directory
+async fn from_models_path(state: &AppState, ident: &str) -> bool {
+    state
+        .sources
+        .lock()
+        .await
+        .get(ident)
+        .is_none_or(|s| !s.files.is_empty())
+}
+
+/// Load a model, replacing the loaded one of the same name in a single step
+///
+/// The new model is built without holding the model lock, so the old one keeps serving
+/// requests while it loads and stays in place if loading fails.
+async fn reload(state: &AppState, ident: &str, source: ModelSource) -> anyhow::Result<()> {
+    let bytes = ModelBytes::read(&source).await?;
+    let loader = state.tts_model.lock().await.loader();
+    let name = ident.to_string();
+    let model = tokio::task::spawn_blocking(move || bytes.build(&loader, &name)).await??;
+    {
+        let mut tts_model = state.tts_model.lock().await;
+        tts_model.unload(ident);
+        tts_model.insert(model);
+    }
+    state.sources.lock().await.insert(ident.to_string(), source);
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use sbv2_core::tts::SynthesizeOptions;
+use sbv2_core::tts_util::{self, PauseOptions, Sentence, SentenceSegmenter};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppQuery, AppResult};
+use crate::metrics;
+use crate::validation::{check_range, check_voice};
+use crate::AppState;
+
+/// Streaming synthesis over a WebSocket
+pub fn router() -> Router<AppState> {
+    Router::new().route("/ws/synthesize", get(stream))
+}
+
+fn length_default() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    ident: String,
+    #[serde(default)]
+    style_id: i32,
+    #[serde(default)]
+    speaker_id: i64,
+    #[serde(default)]
+    sdp_ratio: f32,
+    #[serde(default = "length_default")]
+    length_scale: f32,
+}
+
+/// Messages sent by the client
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// A text fragment, e.g. a token from an LLM stream
+    Text { text: String },
+    /// Synthesize whatever is left in the buffer
+    Flush,
+}
+
+/// Messages sent by the server, each `Sentence` is followed by a binary audio/wav message
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Sentence { text: String },
+    Flushed,
+    Error { message: String },
+}
+
+impl From<ServerMessage> for Message {
+    fn from(message: ServerMessage) -> Self {
+        Message::Text(
+            serde_json::to_string(&message)
+                .expect("server messages are serializable")
+                .into(),
+        )
+    }
+}
+
+async fn stream(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    AppQuery(query): AppQuery<StreamQuery>,
+) -> AppResult<impl IntoResponse> {
+    state.limits.check_length_scale(query.length_scale)?;
+    check_range("sdp_ratio", query.sdp_ratio, 0.0, 1.0)?;
+    check_voice(
+        &*state.tts_model.lock().await,
+        &query.ident,
+        query.style_id,
+        query.speaker_id,
+    )?;
+    Ok(ws.on_upgrade(move |socket| handle_socket(socket, state, query)))
+}
+
+async fn handle_socket(mut socket: WebSocket, state: AppState, query: StreamQuery) {
+    // Sentences are capped so that a stream without terminators cannot grow without bound
+    let mut segmenter =
+        SentenceSegmenter::new(Some(state.limits.max_text_length), PauseOptions::default());
+    while let Some(Ok(message)) = socket.recv().await {
+        let message = match message {
+            Message::Text(text) => serde_json::from_str::<ClientMessage>(text.as_str()),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let (sentences, flush) = match message {
+            Ok(ClientMessage::Text { text }) => (segmenter.push(&text), false),
+            Ok(ClientMessage::Flush) => (segmenter.flush(), true),
+            Err(e) => {
+                let message = ServerMessage::Error {
+                    message: format!("Invalid message: {e}"),
+                };
+                if socket.send(message.into()).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
+        for sentence in sentences {
+            if send_sentence(&mut socket, &state, &query, sentence)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        if flush && socket.send(ServerMessage::Flushed.into()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send_sentence(
+    socket: &mut WebSocket,
+    state: &AppState,
+    query: &StreamQuery,
+    sentence: Sentence,
+) -> Result<(), axum::Error> {
+    log::debug!(
+        "processing stream sentence: text={}, ident={}",
+        sentence.text,
+        query.ident
+    );
+    let options = SynthesizeOptions {
+        sdp_ratio: query.sdp_ratio,
+        length_scale: query.length_scale,
+        ..Default::default()
+    };
+    let result = {
+        let mut tts_model = metrics::lock(&state.tts_model).await;
+        let synthesis = metrics::Synthesis::start(&tts_model, &query.ident, &sentence.text);
+        let result = tts_model.synthesize_samples(
+            &query.ident,
+            &sentence.text,
+            query.style_id,
+            query.speaker_id,
+            options,
+        );
+        synthesis.finish(
+            &result.as_ref().map_err(AppError::from_core),
+            |(samples, sample_rate)| samples.len() as f32 / *sample_rate as f32,
+        );
+        result
+    };
+    let audio = result.and_then(|(mut samples, sample_rate)| {
+        // Keep the pause after the sentence so chunks can be played back to back
+        let pause = (sentence.pause * sample_rate as f32) as usize;
+        samples.resize(samples.len() + pause, 0.0);
+        tts_util::samples_to_wav(&samples, sample_rate, 1)
+    });
+    match audio {
+        Ok(buffer) => {
+            socket
+                .send(
+                    ServerMessage::Sentence {
+                        text: sentence.text,
+                    }
+                    .into(),
+                )
+                .await?;
+            socket.send(Message::Binary(buffer.into())).await
+        }
+        Err(e) => {
+            socket
+                .send(
+                    ServerMessage::Error {
+                        message: e.to_string(),
+                    }
+                    .into(),
+                )
+                .await
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use pyo3::prelude::*;
 mod sbv2;
+mod segmenter;
 pub mod style;
 
 /// sbv2 bindings module
@@ -7,5 +8,6 @@ pub mod style;
 fn sbv2_bindings(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<sbv2::TTSModel>()?;
     m.add_class::<style::StyleVector>()?;
+    m.add_class::<segmenter::SentenceSegmenter>()?;
     Ok(())
 }
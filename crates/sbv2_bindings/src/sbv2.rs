@@ -1,3 +1,4 @@
+use numpy::PyArray1;
 use pyo3::prelude::*;
 use pyo3::types::PyBytes;
 use sbv2_core::tts::{SynthesizeOptions, TTSModelHolder};
@@ -16,6 +17,10 @@ use std::fs;
 ///     BERTモデルのバイナリデータ
 /// tokenizer_bytes : bytes
 ///     トークナイザーのバイナリデータ
+/// max_loaded_models: int | None
+///     同時にVRAMに存在するモデルの数
+/// parallel_sentences: int
+///     複数の文を同時に合成するワーカー数。VITSのスレッド数はワーカー間で分けられる
 #[pyclass]
 pub struct TTSModel {
     pub model: TTSModelHolder,
@@ -23,16 +28,17 @@ pub struct TTSModel {
 
 #[pymethods]
 impl TTSModel {
-    #[pyo3(signature = (bert_model_bytes, tokenizer_bytes, max_loaded_models=None))]
+    #[pyo3(signature = (bert_model_bytes, tokenizer_bytes, max_loaded_models=None, parallel_sentences=1))]
     #[new]
     fn new(
         bert_model_bytes: Vec<u8>,
         tokenizer_bytes: Vec<u8>,
         max_loaded_models: Option<usize>,
+        parallel_sentences: usize,
     ) -> anyhow::Result<Self> {
-        Ok(Self {
-            model: TTSModelHolder::new(bert_model_bytes, tokenizer_bytes, max_loaded_models)?,
-        })
+        let mut model = TTSModelHolder::new(bert_model_bytes, tokenizer_bytes, max_loaded_models)?;
+        model.set_parallel_sentences(parallel_sentences);
+        Ok(Self { model })
     }
 
     /// パスからTTSModelインスタンスを生成する
@@ -45,20 +51,22 @@ impl TTSModel {
     ///     トークナイザーのパス
     /// max_loaded_models: int | None
     ///     同時にVRAMに存在するモデルの数
-    #[pyo3(signature = (bert_model_path, tokenizer_path, max_loaded_models=None))]
+    /// parallel_sentences: int
+    ///     複数の文を同時に合成するワーカー数
+    #[pyo3(signature = (bert_model_path, tokenizer_path, max_loaded_models=None, parallel_sentences=1))]
     #[staticmethod]
     fn from_path(
         bert_model_path: String,
         tokenizer_path: String,
         max_loaded_models: Option<usize>,
+        parallel_sentences: usize,
     ) -> anyhow::Result<Self> {
-        Ok(Self {
-            model: TTSModelHolder::new(
-                fs::read(bert_model_path)?,
-                fs::read(tokenizer_path)?,
-                max_loaded_models,
-            )?,
-        })
+        Self::new(
+            fs::read(bert_model_path)?,
+            fs::read(tokenizer_path)?,
+            max_loaded_models,
+            parallel_sentences,
+        )
     }
 
     /// SBV2ファイルを読み込む
@@ -127,15 +135,22 @@ impl TTSModel {
     ///     識別子
     /// style_id : int
     ///     スタイルID
+    /// speaker_id : int
+    ///     話者ID。複数話者のモデルでなければ0
     /// sdp_ratio : float
     ///     SDP比率
     /// length_scale : float
     ///     音声の長さのスケール
+    /// max_sentence_length : int | None
+    ///     これより長い文を「、」や空白で分割する。Noneの場合は分割しない
+    /// sentence_length_unit : str
+    ///     max_sentence_lengthの単位。"chars"、"tokens"、"morae"のいずれか
     ///
     /// Returns
     /// -------
     /// voice_data : bytes
     ///     音声データ
+    #[pyo3(signature = (text, ident, style_id, speaker_id, sdp_ratio, length_scale, max_sentence_length=Some(80), sentence_length_unit="tokens"))]
     fn synthesize<'p>(
         &'p mut self,
         py: Python<'p>,
@@ -145,6 +160,8 @@ impl TTSModel {
         speaker_id: i64,
         sdp_ratio: f32,
         length_scale: f32,
+        max_sentence_length: Option<usize>,
+        sentence_length_unit: &str,
     ) -> anyhow::Result<Bound<'p, PyBytes>> {
         let data = self.model.easy_synthesize(
             ident.as_str(),
@@ -154,12 +171,70 @@ impl TTSModel {
             SynthesizeOptions {
                 sdp_ratio,
                 length_scale,
+                max_sentence_length,
+                sentence_length_unit: sentence_length_unit.parse()?,
                 ..Default::default()
             },
         )?;
         Ok(PyBytes::new(py, &data))
     }
 
+    /// テキストから音声を合成し、波形をそのまま返す
+    ///
+    /// Parameters
+    /// ----------
+    /// text : str
+    ///     テキスト
+    /// ident : str
+    ///     識別子
+    /// style_id : int
+    ///     スタイルID
+    /// speaker_id : int
+    ///     話者ID。複数話者のモデルでなければ0
+    /// sdp_ratio : float
+    ///     SDP比率
+    /// length_scale : float
+    ///     音声の長さのスケール
+    /// max_sentence_length : int | None
+    ///     これより長い文を「、」や空白で分割する。Noneの場合は分割しない
+    /// sentence_length_unit : str
+    ///     max_sentence_lengthの単位。"chars"、"tokens"、"morae"のいずれか
+    ///
+    /// Returns
+    /// -------
+    /// samples : numpy.ndarray
+    ///     float32の波形データ
+    /// sample_rate : int
+    ///     サンプリングレート
+    #[pyo3(signature = (text, ident, style_id, speaker_id, sdp_ratio, length_scale, max_sentence_length=Some(80), sentence_length_unit="tokens"))]
+    fn synthesize_samples<'p>(
+        &'p mut self,
+        py: Python<'p>,
+        text: String,
+        ident: String,
+        style_id: i32,
+        speaker_id: i64,
+        sdp_ratio: f32,
+        length_scale: f32,
+        max_sentence_length: Option<usize>,
+        sentence_length_unit: &str,
+    ) -> anyhow::Result<(Bound<'p, PyArray1<f32>>, u32)> {
+        let (samples, sample_rate) = self.model.synthesize_samples(
+            ident.as_str(),
+            &text,
+            style_id,
+            speaker_id,
+            SynthesizeOptions {
+                sdp_ratio,
+                length_scale,
+                max_sentence_length,
+                sentence_length_unit: sentence_length_unit.parse()?,
+                ..Default::default()
+            },
+        )?;
+        Ok((PyArray1::from_vec(py, samples), sample_rate))
+    }
+
     fn unload(&mut self, ident: String) -> bool {
         self.model.unload(ident)
     }
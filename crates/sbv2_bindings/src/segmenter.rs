@@ -0,0 +1,61 @@
+use pyo3::prelude::*;
+use sbv2_core::tts_util::{self, PauseOptions};
+
+/// SentenceSegmenter class
+///
+/// ストリーミングで届くテキストを合成できる文に区切るクラス
+///
+/// Parameters
+/// ----------
+/// max_length : int | None
+///     一文の最大文字数
+#[pyclass]
+pub struct SentenceSegmenter {
+    segmenter: tts_util::SentenceSegmenter,
+}
+
+#[pymethods]
+impl SentenceSegmenter {
+    #[pyo3(signature = (max_length=None))]
+    #[new]
+    fn new(max_length: Option<usize>) -> Self {
+        Self {
+            segmenter: tts_util::SentenceSegmenter::new(max_length, PauseOptions::default()),
+        }
+    }
+
+    /// テキストを追加し、完成した文を返す
+    ///
+    /// Parameters
+    /// ----------
+    /// delta : str
+    ///     追加するテキスト
+    ///
+    /// Returns
+    /// -------
+    /// sentences : list[str]
+    ///     完成した文
+    fn push(&mut self, delta: &str) -> Vec<String> {
+        self.segmenter
+            .push(delta)
+            .into_iter()
+            .map(|s| s.text)
+            .collect()
+    }
+
+    /// 残りのテキストをすべて返す
+    ///
+    /// Returns
+    /// -------
+    /// sentences : list[str]
+    ///     残りの文
+    fn flush(&mut self) -> Vec<String> {
+        self.segmenter.flush().into_iter().map(|s| s.text).collect()
+    }
+
+    /// まだ返していないテキスト
+    #[getter]
+    fn pending(&self) -> String {
+        self.segmenter.pending().to_string()
+    }
+}
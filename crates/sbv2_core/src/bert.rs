@@ -1,5 +1,6 @@
 use crate::error::Result;
-use ndarray::{Array2, Ix2};
+use crate::metrics::{self, Stage};
+use ndarray::{s, Array2, Ix2, Ix3};
 use ort::session::Session;
 use ort::value::TensorRef;
 
@@ -8,17 +9,60 @@ pub fn predict(
     token_ids: Vec<i64>,
     attention_masks: Vec<i64>,
 ) -> Result<Array2<f32>> {
-    let outputs = session.run(
-        ort::inputs! {
-            "input_ids" => TensorRef::from_array_view((vec![1, token_ids.len() as i64], token_ids.as_slice()))?,
-            "attention_mask" => TensorRef::from_array_view((vec![1, attention_masks.len() as i64], attention_masks.as_slice()))?,
-        }
-    )?;
+    metrics::time(Stage::Bert, || -> Result<Array2<f32>> {
+        let outputs = session.run(
+            ort::inputs! {
+                "input_ids" => TensorRef::from_array_view((vec![1, token_ids.len() as i64], token_ids.as_slice()))?,
+                "attention_mask" => TensorRef::from_array_view((vec![1, attention_masks.len() as i64], attention_masks.as_slice()))?,
+            }
+        )?;
 
-    let output = outputs["output"]
-        .try_extract_tensor::<f32>()?
-        .into_dimensionality::<Ix2>()?
-        .to_owned();
+        let output = outputs["output"]
+            .try_extract_array::<f32>()?
+            .into_dimensionality::<Ix2>()?
+            .to_owned();
 
-    Ok(output)
+        Ok(output)
+    })
+}
+
+/// Maximum number of sentences run in one batch
+pub const MAX_BATCH_SIZE: usize = 8;
+
+/// Run several sentences in one session call
+///
+/// The inputs are padded to the longest sentence and the features are sliced back per sentence.
+/// Returns `None` if the model drops the batch axis, as models exported without `--batch` do.
+pub fn predict_batch(
+    session: &mut Session,
+    inputs: &[(Vec<i64>, Vec<i64>)],
+) -> Result<Option<Vec<Array2<f32>>>> {
+    let batch_size = inputs.len();
+    let max_len = inputs.iter().map(|(ids, _)| ids.len()).max().unwrap_or(0);
+    let mut token_ids = vec![0; batch_size * max_len];
+    let mut attention_masks = vec![0; batch_size * max_len];
+    for (i, (ids, masks)) in inputs.iter().enumerate() {
+        token_ids[i * max_len..i * max_len + ids.len()].copy_from_slice(ids);
+        attention_masks[i * max_len..i * max_len + masks.len()].copy_from_slice(masks);
+    }
+    let shape = vec![batch_size as i64, max_len as i64];
+    let outputs = metrics::time(Stage::Bert, || {
+        session.run(ort::inputs! {
+            "input_ids" => TensorRef::from_array_view((shape.clone(), token_ids.as_slice()))?,
+            "attention_mask" => TensorRef::from_array_view((shape, attention_masks.as_slice()))?,
+        })
+    })?;
+
+    let output = outputs["output"].try_extract_array::<f32>()?;
+    if output.ndim() != 3 || output.shape()[0] != batch_size {
+        return Ok(None);
+    }
+    let output = output.into_dimensionality::<Ix3>()?;
+    Ok(Some(
+        inputs
+            .iter()
+            .enumerate()
+            .map(|(i, (ids, _))| output.slice(s![i, ..ids.len(), ..]).to_owned())
+            .collect(),
+    ))
 }
@@ -1,9 +1,10 @@
 use crate::error::{Error, Result};
-use crate::mora::{MORA_KATA_TO_MORA_PHONEMES, VOWELS};
+use crate::mora::{MORA_KATA_TO_MORA_PHONEMES, MORA_PHONEMES_TO_MORA_KATA, VOWELS};
 use crate::norm::{replace_punctuation, PUNCTUATIONS};
 use jpreprocess::{kind, DefaultTokenizer, JPreprocess, SystemDictionaryConfig, UserDictionary};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Serialize;
 use std::cmp::Reverse;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -68,6 +69,34 @@ impl JTalk {
     }
 }
 
+/// A mora of an accent phrase
+///
+/// # Fields
+/// - `text`: Katakana of the mora
+/// - `consonant`: Consonant phoneme
+/// - `vowel`: Vowel phoneme, uppercase if devoiced, `N` for ン and `cl` for ッ
+#[derive(Debug, Clone, Serialize)]
+pub struct AccentMora {
+    pub text: String,
+    pub consonant: Option<String>,
+    pub vowel: String,
+}
+
+/// An accent phrase as analyzed by OpenJTalk
+///
+/// # Fields
+/// - `moras`: Moras of the phrase
+/// - `accent`: Position of the accent nucleus, counted from 1
+/// - `pause`: Whether a pause follows the phrase
+/// - `is_interrogative`: Whether the phrase is a question
+#[derive(Debug, Clone, Serialize)]
+pub struct AccentPhrase {
+    pub moras: Vec<AccentMora>,
+    pub accent: usize,
+    pub pause: bool,
+    pub is_interrogative: bool,
+}
+
 static KATAKANA_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\u30A0-\u30FF]+").unwrap());
 static MORA_PATTERN: Lazy<Vec<String>> = Lazy::new(|| {
     let mut sorted_keys: Vec<String> = MORA_KATA_TO_MORA_PHONEMES.keys().cloned().collect();
@@ -345,6 +374,73 @@ impl JTalkProcess {
         Ok(results)
     }
 
+    /// Split the text into accent phrases
+    pub fn accent_phrases(&self) -> Result<Vec<AccentPhrase>> {
+        let labels = self.jpreprocess.make_label(self.parsed.clone());
+
+        let mut phrases: Vec<AccentPhrase> = vec![];
+        let mut consonant = None;
+        for label in &labels {
+            let Some(phoneme) = label.phoneme.c.clone() else {
+                continue;
+            };
+            match phoneme.as_str() {
+                "sil" => continue,
+                "pau" => {
+                    if let Some(last) = phrases.last_mut() {
+                        last.pause = true;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            if !matches!(
+                phoneme.as_str(),
+                "a" | "i" | "u" | "e" | "o" | "A" | "I" | "U" | "E" | "O" | "N" | "cl"
+            ) {
+                consonant = Some(phoneme);
+                continue;
+            }
+            let (Some(mora), Some(phrase)) = (&label.mora, &label.accent_phrase_curr) else {
+                continue;
+            };
+            let vowel = phoneme;
+            let kata_vowel = match vowel.as_str() {
+                "cl" => "q".to_string(),
+                "N" => "N".to_string(),
+                v => v.to_lowercase(),
+            };
+            let consonant = consonant.take();
+            let text = MORA_PHONEMES_TO_MORA_KATA
+                .get(&(consonant.clone(), kata_vowel))
+                .cloned()
+                .unwrap_or_default();
+            if mora.position_forward == 1 || phrases.is_empty() {
+                phrases.push(AccentPhrase {
+                    moras: vec![],
+                    accent: if phrase.accent_position == 0 {
+                        phrase.mora_count as usize
+                    } else {
+                        phrase.accent_position as usize
+                    },
+                    pause: false,
+                    is_interrogative: phrase.is_interrogative,
+                });
+            }
+            if let Some(last) = phrases.last_mut() {
+                last.moras.push(AccentMora {
+                    text,
+                    consonant,
+                    vowel,
+                });
+            }
+        }
+        if let Some(last) = phrases.last_mut() {
+            last.pause = false;
+        }
+        Ok(phrases)
+    }
+
     fn g2p_prosody(&self) -> Result<Vec<String>> {
         let labels = self.jpreprocess.make_label(self.parsed.clone());
 
@@ -2,15 +2,20 @@
 pub mod bert;
 pub mod error;
 pub mod jtalk;
+pub mod metrics;
 #[cfg(feature = "std")]
 pub mod model;
 pub mod mora;
 pub mod nlp;
 pub mod norm;
+pub mod pitch;
+pub mod postprocess;
 pub mod sbv2file;
 pub mod style;
+pub mod subtitle;
 pub mod tokenizer;
 #[cfg(feature = "std")]
 pub mod tts;
 pub mod tts_util;
 pub mod utils;
+pub mod viseme;
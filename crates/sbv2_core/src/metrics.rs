@@ -0,0 +1,55 @@
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+/// Stage of the synthesis pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Number reading and text normalization
+    Normalization,
+    /// Phoneme, tone and kana conversion
+    G2p,
+    /// BERT feature extraction
+    Bert,
+    /// VITS inference
+    Vits,
+    /// Audio encoding
+    Encoding,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Normalization => "normalization",
+            Stage::G2p => "g2p",
+            Stage::Bert => "bert",
+            Stage::Vits => "vits",
+            Stage::Encoding => "encoding",
+        }
+    }
+}
+
+static OBSERVER: OnceCell<fn(Stage, Duration)> = OnceCell::new();
+
+/// Set the function called with the time spent in each stage
+///
+/// Returns `false` if an observer was already set.
+pub fn set_stage_observer(observer: fn(Stage, Duration)) -> bool {
+    OBSERVER.set(observer).is_ok()
+}
+
+/// Run `f` and report the time it took as `stage`
+///
+/// Nothing is measured without an observer or without the `std` feature,
+/// as wasm has no clock.
+pub fn time<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "std")]
+    if let Some(observer) = OBSERVER.get() {
+        let start = std::time::Instant::now();
+        let result = f();
+        observer(stage, start.elapsed());
+        return result;
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = stage;
+    f()
+}
@@ -1,52 +1,394 @@
-use crate::error::Result;
-use ndarray::{array, Array1, Array2, Array3, Axis, Ix3};
-use ort::session::{builder::GraphOptimizationLevel, Session};
+use crate::error::{Error, Result};
+use crate::metrics::{self, Stage};
+use ndarray::{array, Array1, Array2, Array3, Axis, Ix3, Ix4};
+use ort::execution_providers::{ExecutionProvider, ExecutionProviderDispatch};
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::session::Session;
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
 
-#[allow(clippy::vec_init_then_push, unused_variables)]
-pub fn load_model<P: AsRef<[u8]>>(model_file: P, bert: bool) -> Result<Session> {
+/// Graph optimization level of an ONNX Runtime session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptimizationLevel {
+    Disable,
+    Level1,
+    Level2,
+    Level3,
+}
+
+impl From<OptimizationLevel> for GraphOptimizationLevel {
+    fn from(value: OptimizationLevel) -> Self {
+        match value {
+            OptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+            OptimizationLevel::Level1 => GraphOptimizationLevel::Level1,
+            OptimizationLevel::Level2 => GraphOptimizationLevel::Level2,
+            OptimizationLevel::Level3 => GraphOptimizationLevel::Level3,
+        }
+    }
+}
+
+impl std::str::FromStr for OptimizationLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "disable" | "0" => Ok(OptimizationLevel::Disable),
+            "level1" | "1" => Ok(OptimizationLevel::Level1),
+            "level2" | "2" => Ok(OptimizationLevel::Level2),
+            "level3" | "3" => Ok(OptimizationLevel::Level3),
+            _ => Err(Error::ValueError(format!(
+                "Unknown optimization level: {s}"
+            ))),
+        }
+    }
+}
+
+/// ONNX Runtime session options
+///
+/// # Fields
+/// - `intra_threads`: Threads used to parallelize a single operator
+/// - `inter_threads`: Threads used to run independent operators in parallel
+/// - `parallel_execution`: Run independent operators in parallel
+/// - `optimization_level`: Graph optimization level
+/// - `memory_arena`: Use an arena for CPU allocations, trading memory for speed
+/// - `memory_pattern`: Preallocate memory based on the shapes of previous runs
+/// - `deterministic`: Use deterministic kernels where available
+/// - `providers`: Execution providers to try in order, CPU is always used as the fallback
+/// - `cache_dir`: Directory where optimized graphs are saved and loaded from on later runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionOptions {
+    pub intra_threads: usize,
+    pub inter_threads: usize,
+    pub parallel_execution: bool,
+    pub optimization_level: OptimizationLevel,
+    pub memory_arena: bool,
+    pub memory_pattern: bool,
+    pub deterministic: bool,
+    pub providers: Vec<Provider>,
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            intra_threads: num_cpus::get_physical(),
+            inter_threads: num_cpus::get_physical(),
+            parallel_execution: true,
+            optimization_level: OptimizationLevel::Level3,
+            memory_arena: true,
+            memory_pattern: true,
+            deterministic: false,
+            providers: Provider::compiled(),
+            cache_dir: None,
+        }
+    }
+}
+
+/// Session options for the BERT and VITS models
+///
+/// # Fields
+/// - `bert`: Options for the BERT session
+/// - `vits`: Options for the VITS sessions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionConfig {
+    pub bert: SessionOptions,
+    pub vits: SessionOptions,
+}
+
+/// Execution provider of an ONNX Runtime session
+///
+/// Providers other than `Cpu` need the cargo feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    Cpu,
+    Cuda,
+    /// Only used for the BERT session
+    TensorRT,
+    DirectML,
+    CoreML,
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Provider::Cpu => "cpu",
+            Provider::Cuda => "cuda",
+            Provider::TensorRT => "tensorrt",
+            Provider::DirectML => "directml",
+            Provider::CoreML => "coreml",
+        })
+    }
+}
+
+impl std::str::FromStr for Provider {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cpu" => Ok(Provider::Cpu),
+            "cuda" => Ok(Provider::Cuda),
+            "tensorrt" => Ok(Provider::TensorRT),
+            "directml" => Ok(Provider::DirectML),
+            "coreml" => Ok(Provider::CoreML),
+            _ => Err(Error::ValueError(format!(
+                "Unknown execution provider: {s}"
+            ))),
+        }
+    }
+}
+
+impl Provider {
+    /// Providers enabled by cargo features, in order of preference
+    #[allow(clippy::vec_init_then_push)]
+    pub fn compiled() -> Vec<Provider> {
+        let mut providers = Vec::new();
+        #[cfg(feature = "tensorrt")]
+        providers.push(Provider::TensorRT);
+        #[cfg(feature = "cuda")]
+        providers.push(Provider::Cuda);
+        #[cfg(feature = "directml")]
+        providers.push(Provider::DirectML);
+        #[cfg(feature = "coreml")]
+        providers.push(Provider::CoreML);
+        providers.push(Provider::Cpu);
+        providers
+    }
+
+    /// Parse a comma separated list such as `cuda,cpu`
+    pub fn parse_list(s: &str) -> Result<Vec<Provider>> {
+        s.split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| p.parse())
+            .collect()
+    }
+}
+
+#[allow(dead_code)]
+fn push_if_available<E>(
+    exp: &mut Vec<ExecutionProviderDispatch>,
+    provider: Provider,
+    execution_provider: E,
+) where
+    E: ExecutionProvider + Into<ExecutionProviderDispatch>,
+{
+    match execution_provider.is_available() {
+        Ok(true) => exp.push(execution_provider.into()),
+        Ok(false) => log::warn!("{provider} is not available on this host, falling back"),
+        Err(e) => log::warn!("Could not check whether {provider} is available, falling back: {e}"),
+    }
+}
+
+/// Whether the first input of the model has a dynamic batch axis, as in models exported with `--batch`
+#[cfg(feature = "tensorrt")]
+fn has_dynamic_batch(model_file: &[u8]) -> Result<bool> {
+    let session = Session::builder()?
+        .with_optimization_level(GraphOptimizationLevel::Disable)?
+        .commit_from_memory(model_file)?;
+    Ok(session.inputs.first().is_some_and(|input| {
+        matches!(
+            &input.input_type,
+            ort::value::ValueType::Tensor { shape, .. } if shape.first() == Some(&-1)
+        )
+    }))
+}
+
+/// `batch` tells whether the BERT model accepts more than one sentence per run
+#[allow(unused_variables)]
+fn execution_providers(
+    options: &SessionOptions,
+    bert: bool,
+    batch: bool,
+) -> Vec<ExecutionProviderDispatch> {
     let mut exp = Vec::new();
-    #[cfg(feature = "tensorrt")]
-    {
-        if bert {
-            exp.push(
-                ort::execution_providers::TensorRTExecutionProvider::default()
-                    .with_fp16(true)
-                    .with_profile_min_shapes("input_ids:1x1,attention_mask:1x1")
-                    .with_profile_max_shapes("input_ids:1x100,attention_mask:1x100")
-                    .with_profile_opt_shapes("input_ids:1x25,attention_mask:1x25")
-                    .build(),
-            );
+    for &provider in &options.providers {
+        match provider {
+            Provider::Cpu => break,
+            #[cfg(feature = "tensorrt")]
+            Provider::TensorRT => {
+                if bert {
+                    // The profile must match static axes, so only widen the batch when it is dynamic
+                    let max_batch = if batch { crate::bert::MAX_BATCH_SIZE } else { 1 };
+                    push_if_available(
+                        &mut exp,
+                        provider,
+                        ort::execution_providers::TensorRTExecutionProvider::default()
+                            .with_fp16(true)
+                            .with_profile_min_shapes("input_ids:1x1,attention_mask:1x1")
+                            .with_profile_max_shapes(format!(
+                                "input_ids:{max_batch}x100,attention_mask:{max_batch}x100"
+                            ))
+                            .with_profile_opt_shapes("input_ids:1x25,attention_mask:1x25"),
+                    );
+                }
+            }
+            #[cfg(feature = "cuda")]
+            Provider::Cuda => {
+                #[allow(unused_mut)]
+                let mut cuda = ort::execution_providers::CUDAExecutionProvider::default()
+                    .with_conv_algorithm_search(
+                        ort::execution_providers::cuda::CuDNNConvAlgorithmSearch::Default,
+                    );
+                #[cfg(feature = "cuda_tf32")]
+                {
+                    cuda = cuda.with_tf32(true);
+                }
+                push_if_available(&mut exp, provider, cuda);
+            }
+            #[cfg(feature = "directml")]
+            Provider::DirectML => push_if_available(
+                &mut exp,
+                provider,
+                ort::execution_providers::DirectMLExecutionProvider::default(),
+            ),
+            #[cfg(feature = "coreml")]
+            Provider::CoreML => push_if_available(
+                &mut exp,
+                provider,
+                ort::execution_providers::CoreMLExecutionProvider::default(),
+            ),
+            #[allow(unreachable_patterns)]
+            _ => log::warn!(
+                "{provider} is not enabled in this build, falling back (enable the `{provider}` feature to use it)"
+            ),
         }
     }
-    #[cfg(feature = "cuda")]
+    exp.push(
+        ort::execution_providers::CPUExecutionProvider::default()
+            .with_arena_allocator(options.memory_arena)
+            .build(),
+    );
+    exp
+}
+
+fn session_builder(
+    options: &SessionOptions,
+    bert: bool,
+    batch: bool,
+    optimization_level: OptimizationLevel,
+) -> Result<SessionBuilder> {
+    Ok(Session::builder()?
+        .with_execution_providers(execution_providers(options, bert, batch))?
+        .with_optimization_level(optimization_level.into())?
+        .with_intra_threads(options.intra_threads)?
+        .with_parallel_execution(options.parallel_execution)?
+        .with_inter_threads(options.inter_threads)?
+        .with_memory_pattern(options.memory_pattern)?
+        .with_deterministic_compute(options.deterministic)?)
+}
+
+/// CPU features that change the kernels chosen by Level3 optimization
+fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features = vec![std::env::consts::ARCH];
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
-        #[allow(unused_mut)]
-        let mut cuda = ort::execution_providers::CUDAExecutionProvider::default()
-            .with_conv_algorithm_search(
-                ort::execution_providers::cuda::CUDAExecutionProviderCuDNNConvAlgoSearch::Default,
-            );
-        #[cfg(feature = "cuda_tf32")]
-        {
-            cuda = cuda.with_tf32(true);
+        macro_rules! detect {
+            ($($feature:tt),*) => {
+                $(if std::arch::is_x86_feature_detected!($feature) {
+                    features.push($feature);
+                })*
+            };
         }
-        exp.push(cuda.build());
+        detect!("sse4.1", "sse4.2", "avx", "avx2", "fma", "f16c", "avx512f");
     }
-    #[cfg(feature = "directml")]
+    #[cfg(target_arch = "aarch64")]
     {
-        exp.push(ort::execution_providers::DirectMLExecutionProvider::default().build());
+        macro_rules! detect {
+            ($($feature:tt),*) => {
+                $(if std::arch::is_aarch64_feature_detected!($feature) {
+                    features.push($feature);
+                })*
+            };
+        }
+        detect!("neon", "dotprod", "fp16", "sve");
+    }
+    features
+}
+
+/// Whether the optimized graph can be saved
+///
+/// ONNX Runtime cannot save graphs with nodes compiled by providers such as TensorRT or CoreML.
+fn cacheable(options: &SessionOptions) -> bool {
+    options
+        .providers
+        .iter()
+        .take_while(|p| **p != Provider::Cpu)
+        .all(|p| *p == Provider::Cuda)
+}
+
+/// File name of the optimized graph, from the model bytes, the options affecting optimization
+/// and the host CPU features, so that hosts sharing a cache do not overwrite each other's graphs
+fn cache_key(model_file: &[u8], bert: bool, options: &SessionOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model_file);
+    hasher.update(
+        format!(
+            "{bert}:{:?}:{:?}:{}:{}",
+            options.optimization_level,
+            options.providers,
+            options.deterministic,
+            cpu_features().join(",")
+        )
+        .as_bytes(),
+    );
+    let hash: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("{hash}.onnx")
+}
+
+pub fn load_model<P: AsRef<[u8]>>(
+    model_file: P,
+    bert: bool,
+    options: &SessionOptions,
+) -> Result<Session> {
+    let model_file = model_file.as_ref();
+    #[cfg(feature = "tensorrt")]
+    let batch =
+        bert && options.providers.contains(&Provider::TensorRT) && has_dynamic_batch(model_file)?;
+    #[cfg(not(feature = "tensorrt"))]
+    let batch = false;
+    let Some(cache_dir) = options.cache_dir.as_ref().filter(|_| cacheable(options)) else {
+        return Ok(
+            session_builder(options, bert, batch, options.optimization_level)?
+                .commit_from_memory(model_file)?,
+        );
+    };
+    let path = cache_dir.join(cache_key(model_file, bert, options));
+    if path.exists() {
+        match session_builder(options, bert, batch, OptimizationLevel::Disable)?
+            .commit_from_file(&path)
+        {
+            Ok(session) => return Ok(session),
+            Err(e) => {
+                log::warn!("Ignoring broken cached model {}: {e}", path.display());
+                std::fs::remove_file(&path).ok();
+            }
+        }
     }
-    #[cfg(feature = "coreml")]
+    std::fs::create_dir_all(cache_dir)?;
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let session = match session_builder(options, bert, batch, options.optimization_level)?
+        .with_optimized_model_path(&tmp)?
+        .commit_from_memory(model_file)
     {
-        exp.push(ort::execution_providers::CoreMLExecutionProvider::default().build());
+        Ok(session) => session,
+        Err(e) => {
+            log::warn!("Could not save optimized model, loading without the cache: {e}");
+            std::fs::remove_file(&tmp).ok();
+            return Ok(
+                session_builder(options, bert, batch, options.optimization_level)?
+                    .commit_from_memory(model_file)?,
+            );
+        }
+    };
+    if let Err(e) = std::fs::rename(&tmp, &path) {
+        log::warn!("Could not cache optimized model to {}: {e}", path.display());
+        std::fs::remove_file(&tmp).ok();
     }
-    exp.push(ort::execution_providers::CPUExecutionProvider::default().build());
-    Ok(Session::builder()?
-        .with_execution_providers(exp)?
-        .with_optimization_level(GraphOptimizationLevel::Level3)?
-        .with_intra_threads(num_cpus::get_physical())?
-        .with_parallel_execution(true)?
-        .with_inter_threads(num_cpus::get_physical())?
-        .commit_from_memory(model_file.as_ref())?)
+    Ok(session)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -54,7 +396,7 @@ pub fn synthesize(
     session: &mut Session,
     bert_ori: Array2<f32>,
     x_tst: Array1<i64>,
-    mut spk_ids: Array1<i64>,
+    spk_ids: Array1<i64>,
     tones: Array1<i64>,
     lang_ids: Array1<i64>,
     style_vector: Array1<f32>,
@@ -63,6 +405,40 @@ pub fn synthesize(
     noise_scale: f32,
     noise_scale_w: f32,
 ) -> Result<Array3<f32>> {
+    let (audio_array, _) = synthesize_with_durations(
+        session,
+        bert_ori,
+        x_tst,
+        spk_ids,
+        tones,
+        lang_ids,
+        style_vector,
+        sdp_ratio,
+        length_scale,
+        noise_scale,
+        noise_scale_w,
+    )?;
+    Ok(audio_array)
+}
+
+/// Synthesize and also return the predicted number of frames for each input phoneme
+///
+/// Durations are read from a `durations` output (`[1, 1, x_tst]`) or derived from an
+/// `attn` output (`[1, 1, frames, x_tst]`). `None` is returned if the model exposes neither.
+#[allow(clippy::too_many_arguments)]
+pub fn synthesize_with_durations(
+    session: &mut Session,
+    bert_ori: Array2<f32>,
+    x_tst: Array1<i64>,
+    mut spk_ids: Array1<i64>,
+    tones: Array1<i64>,
+    lang_ids: Array1<i64>,
+    style_vector: Array1<f32>,
+    sdp_ratio: f32,
+    length_scale: f32,
+    noise_scale: f32,
+    noise_scale_w: f32,
+) -> Result<(Array3<f32>, Option<Array1<f32>>)> {
     let bert_ori = bert_ori.insert_axis(Axis(0));
     let bert_ori = bert_ori.as_standard_layout();
     let bert = ort::value::TensorRef::from_array_view(&bert_ori)?;
@@ -88,24 +464,47 @@ pub fn synthesize(
     let noise_scale_w = vec![noise_scale_w];
     let noise_scale_w =
         ort::value::TensorRef::from_array_view((vec![1_i64], noise_scale_w.as_slice()))?;
-    let outputs = session.run(ort::inputs! {
-        "x_tst" =>  x_tst,
-        "x_tst_lengths" => x_tst_lengths,
-        "sid" => sid,
-        "tones" => tones,
-        "language" => lang_ids,
-        "bert" => bert,
-        "style_vec" => style_vector,
-        "sdp_ratio" => sdp_ratio,
-        "length_scale" => length_scale,
-        "noise_scale" => noise_scale,
-        "noise_scale_w" => noise_scale_w,
+    let outputs = metrics::time(Stage::Vits, || {
+        session.run(ort::inputs! {
+            "x_tst" => x_tst,
+            "x_tst_lengths" => x_tst_lengths,
+            "sid" => sid,
+            "tones" => tones,
+            "language" => lang_ids,
+            "bert" => bert,
+            "style_vec" => style_vector,
+            "sdp_ratio" => sdp_ratio,
+            "length_scale" => length_scale,
+            "noise_scale" => noise_scale,
+            "noise_scale_w" => noise_scale_w,
+        })
     })?;
 
     let audio_array = outputs["output"]
-        .try_extract_tensor::<f32>()?
+        .try_extract_array::<f32>()?
         .into_dimensionality::<Ix3>()?
         .to_owned();
 
-    Ok(audio_array)
+    let durations = if let Some(durations) = outputs.get("durations") {
+        Some(
+            durations
+                .try_extract_array::<f32>()?
+                .iter()
+                .copied()
+                .collect(),
+        )
+    } else if let Some(attn) = outputs.get("attn") {
+        Some(
+            attn.try_extract_array::<f32>()?
+                .into_dimensionality::<Ix4>()?
+                .sum_axis(Axis(2))
+                .iter()
+                .copied()
+                .collect(),
+        )
+    } else {
+        None
+    };
+
+    Ok((audio_array, durations))
 }
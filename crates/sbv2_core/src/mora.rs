@@ -37,4 +37,15 @@ pub static MORA_KATA_TO_MORA_PHONEMES: Lazy<HashMap<String, (Option<String>, Str
         map
     });
 
+/// Katakana of each (consonant, vowel) pair, preferring the minimum mora list
+pub static MORA_PHONEMES_TO_MORA_KATA: Lazy<HashMap<(Option<String>, String), String>> =
+    Lazy::new(|| {
+        let mut map = HashMap::new();
+        for mora in MORA_LIST_MINIMUM.iter().chain(MORA_LIST_ADDITIONAL.iter()) {
+            map.entry((mora.consonant.clone(), mora.vowel.clone()))
+                .or_insert_with(|| mora.mora.clone());
+        }
+        map
+    });
+
 pub const VOWELS: [&str; 6] = ["a", "i", "u", "e", "o", "N"];
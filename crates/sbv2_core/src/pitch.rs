@@ -0,0 +1,243 @@
+const MIN_F0: f32 = 70.0;
+const MAX_F0: f32 = 800.0;
+const VOICING_THRESHOLD: f32 = 0.45;
+const DECIMATION: usize = 4;
+const OCTAVE_TOLERANCE: f32 = 0.9;
+
+/// Estimated fundamental frequency of each analysis frame
+struct PitchTrack {
+    hop: usize,
+    f0: Vec<Option<f32>>,
+}
+
+impl PitchTrack {
+    fn at(&self, sample: usize) -> Option<f32> {
+        self.f0.get(sample / self.hop).copied().flatten()
+    }
+}
+
+fn nccf(x: &[f32], start: usize, lag: usize, window: usize) -> f32 {
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (a, b) in x[start..start + window]
+        .iter()
+        .zip(&x[start + lag..start + lag + window])
+    {
+        xy += a * b;
+        xx += a * a;
+        yy += b * b;
+    }
+    if xx * yy > 0.0 {
+        xy / (xx * yy).sqrt()
+    } else {
+        0.0
+    }
+}
+
+/// Find the lag with the highest correlation
+///
+/// Multiples of the period correlate about as well as the period itself, so the shortest lag
+/// scoring within `OCTAVE_TOLERANCE` of the best is taken and moved to its local peak.
+fn best_lag(x: &[f32], start: usize, lags: (usize, usize), window: usize) -> (usize, f32) {
+    let scores: Vec<(usize, f32)> = (lags.0..=lags.1)
+        .filter(|lag| start + lag + window <= x.len())
+        .map(|lag| (lag, nccf(x, start, lag, window)))
+        .collect();
+    let best = scores.iter().fold(0f32, |m, c| m.max(c.1));
+    let Some(mut i) = scores
+        .iter()
+        .position(|c| best > 0.0 && c.1 >= best * OCTAVE_TOLERANCE)
+    else {
+        return (0, 0.0);
+    };
+    while i + 1 < scores.len() && scores[i + 1].1 > scores[i].1 {
+        i += 1;
+    }
+    scores[i]
+}
+
+/// Normalized cross-correlation pitch tracker
+///
+/// The lag search runs on a decimated signal and is refined at the full rate.
+fn track_pitch(samples: &[f32], sample_rate: u32) -> PitchTrack {
+    let sr = sample_rate as f32;
+    let hop = (sample_rate / 200) as usize;
+    let max_lag = (sr / MIN_F0) as usize;
+    let min_lag = (sr / MAX_F0) as usize;
+    let decimated: Vec<f32> = samples
+        .chunks(DECIMATION)
+        .map(|c| c.iter().sum::<f32>() / c.len() as f32)
+        .collect();
+    let silence = 10f32.powf(-50.0 / 20.0);
+    let mut f0 = vec![];
+    let mut start = 0;
+    while start + 2 * max_lag <= samples.len() {
+        let frame = &samples[start..start + max_lag];
+        let rms = (frame.iter().map(|x| x * x).sum::<f32>() / frame.len() as f32).sqrt();
+        let (coarse, _) = best_lag(
+            &decimated,
+            start / DECIMATION,
+            (min_lag / DECIMATION, max_lag / DECIMATION),
+            max_lag / DECIMATION,
+        );
+        let (lag, score) = best_lag(
+            samples,
+            start,
+            (
+                (coarse * DECIMATION)
+                    .saturating_sub(DECIMATION)
+                    .max(min_lag),
+                (coarse * DECIMATION + DECIMATION).min(max_lag),
+            ),
+            max_lag,
+        );
+        f0.push(if rms > silence && score > VOICING_THRESHOLD && lag > 0 {
+            Some(sr / lag as f32)
+        } else {
+            None
+        });
+        start += hop;
+    }
+    PitchTrack { hop, f0 }
+}
+
+/// Shift the pitch and scale the intonation while keeping the duration (TD-PSOLA)
+///
+/// - `pitch_scale`: Pitch shift in octaves (`0.0` keeps the pitch)
+/// - `intonation_scale`: Scale of the log-f0 deviation from the mean (`1.0` keeps the intonation)
+pub fn modify(
+    samples: &[f32],
+    sample_rate: u32,
+    pitch_scale: f32,
+    intonation_scale: f32,
+) -> Vec<f32> {
+    if (pitch_scale == 0.0 && intonation_scale == 1.0) || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let track = track_pitch(samples, sample_rate);
+    let voiced: Vec<f32> = track.f0.iter().flatten().map(|f| f.ln()).collect();
+    if voiced.is_empty() {
+        return samples.to_vec();
+    }
+    let mean = voiced.iter().sum::<f32>() / voiced.len() as f32;
+    let factor = |f0: f32| {
+        let target = mean + (f0.ln() - mean) * intonation_scale + pitch_scale * 2f32.ln();
+        (target - f0.ln()).exp().clamp(0.5, 2.0)
+    };
+
+    // analysis pitch marks, snapped to the positive peak of each period
+    let unvoiced_period = (sample_rate / 200) as usize;
+    let mut marks: Vec<(usize, usize, f32)> = vec![];
+    let mut t = 0;
+    while t < samples.len() {
+        let (period, f) = match track.at(t) {
+            Some(f0) => ((sample_rate as f32 / f0) as usize, factor(f0)),
+            None => (unvoiced_period, 1.0),
+        };
+        let mark = if track.at(t).is_some() {
+            let lo = t.saturating_sub(period / 4);
+            let hi = (t + period / 4).min(samples.len() - 1);
+            (lo..=hi)
+                .max_by(|&a, &b| samples[a].total_cmp(&samples[b]))
+                .unwrap_or(t)
+                .max(marks.last().map_or(0, |m| m.0 + period / 2))
+        } else {
+            t
+        };
+        marks.push((mark, period, f));
+        t = mark + period;
+    }
+
+    // overlap-add the windowed periods at the synthesis marks
+    let mut output = vec![0.0; samples.len()];
+    let mut weights = vec![0.0; samples.len()];
+    let mut k = 0;
+    let mut ts = marks[0].0 as f32;
+    while (ts as usize) < samples.len() {
+        while k + 1 < marks.len()
+            && (marks[k + 1].0 as f32 - ts).abs() <= (marks[k].0 as f32 - ts).abs()
+        {
+            k += 1;
+        }
+        let (mark, period, f) = marks[k];
+        let center = ts as usize;
+        for i in 0..2 * period {
+            let (Some(src), Some(dst)) = (
+                (mark + i).checked_sub(period),
+                (center + i).checked_sub(period),
+            ) else {
+                continue;
+            };
+            if src >= samples.len() || dst >= samples.len() {
+                continue;
+            }
+            let w = 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / period as f32).cos();
+            output[dst] += samples[src] * w;
+            weights[dst] += w;
+        }
+        ts += (period as f32 / f).max(1.0);
+    }
+    for (o, w) in output.iter_mut().zip(weights) {
+        if w > 1e-2 {
+            *o /= w;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, seconds: f32, sample_rate: u32) -> Vec<f32> {
+        (0..(seconds * sample_rate as f32) as usize)
+            .map(|i| {
+                0.5 * (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    /// Median f0 of the voiced frames
+    fn median_f0(samples: &[f32], sample_rate: u32) -> f32 {
+        let mut f0: Vec<f32> = track_pitch(samples, sample_rate)
+            .f0
+            .into_iter()
+            .flatten()
+            .collect();
+        f0.sort_by(f32::total_cmp);
+        f0[f0.len() / 2]
+    }
+
+    #[test]
+    fn track_pitch_estimates_sine_f0() {
+        for frequency in [110.0, 220.0, 440.0] {
+            let samples = sine(frequency, 0.5, 24000);
+            let track = track_pitch(&samples, 24000);
+            let voiced: Vec<f32> = track.f0.iter().flatten().copied().collect();
+            assert!(voiced.len() * 10 >= track.f0.len() * 9);
+            for f0 in voiced {
+                assert!((f0 / frequency - 1.0).abs() < 0.02, "{f0} for {frequency}");
+            }
+        }
+        assert!(track_pitch(&[0.0; 12000], 24000)
+            .f0
+            .iter()
+            .all(Option::is_none));
+    }
+
+    #[test]
+    fn modify_keeps_length() {
+        let samples = sine(220.0, 0.5, 24000);
+        assert_eq!(modify(&samples, 24000, 0.0, 1.0), samples);
+        assert_eq!(modify(&samples, 24000, 0.5, 1.0).len(), samples.len());
+        assert_eq!(modify(&samples, 24000, -0.5, 2.0).len(), samples.len());
+    }
+
+    #[test]
+    fn modify_shifts_pitch() {
+        let samples = sine(200.0, 0.5, 24000);
+        let shifted = modify(&samples, 24000, 0.5, 1.0);
+        let expected = 200.0 * 2f32.sqrt();
+        let f0 = median_f0(&shifted, 24000);
+        assert!((f0 / expected - 1.0).abs() < 0.05, "{f0}");
+    }
+}
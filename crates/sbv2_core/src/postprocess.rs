@@ -0,0 +1,354 @@
+use std::f32::consts::PI;
+
+/// Loudness normalization target
+#[derive(Debug, Clone, Copy)]
+pub enum Normalization {
+    /// Integrated loudness in LUFS (ITU-R BS.1770)
+    Loudness(f32),
+    /// Sample peak in dBFS
+    Peak(f32),
+}
+
+/// Post-processing options
+///
+/// # Fields
+/// - `normalize`: Normalize to a target loudness or peak
+/// - `trim_silence`: Trim leading and trailing audio quieter than this level in dBFS
+/// - `fade_in`: Fade-in length in seconds
+/// - `fade_out`: Fade-out length in seconds
+/// - `prevent_clipping`: Scale the audio down if its peak exceeds full scale
+#[derive(Debug, Clone, Default)]
+pub struct PostProcessOptions {
+    pub normalize: Option<Normalization>,
+    pub trim_silence: Option<f32>,
+    pub fade_in: f32,
+    pub fade_out: f32,
+    pub prevent_clipping: bool,
+}
+
+impl PostProcessOptions {
+    /// Whether any post-processing is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.normalize.is_some()
+            || self.trim_silence.is_some()
+            || self.fade_in > 0.0
+            || self.fade_out > 0.0
+            || self.prevent_clipping
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Apply post-processing in place and return the number of samples trimmed from the start
+pub fn process(samples: &mut Vec<f32>, options: &PostProcessOptions, sample_rate: u32) -> usize {
+    let trimmed = if let Some(threshold) = options.trim_silence {
+        trim_silence(samples, threshold, sample_rate)
+    } else {
+        0
+    };
+    match options.normalize {
+        Some(Normalization::Loudness(target)) => normalize_loudness(samples, target, sample_rate),
+        Some(Normalization::Peak(target)) => normalize_peak(samples, target),
+        None => {}
+    }
+    fade(samples, options.fade_in, options.fade_out, sample_rate);
+    if options.prevent_clipping {
+        prevent_clipping(samples);
+    }
+    trimmed
+}
+
+/// Remove leading and trailing 10ms frames whose RMS is below `threshold` dBFS
+///
+/// Returns the number of samples removed from the start. Audio that is silent throughout is
+/// left unchanged.
+pub fn trim_silence(samples: &mut Vec<f32>, threshold: f32, sample_rate: u32) -> usize {
+    let frame = (sample_rate as usize / 100).max(1);
+    let threshold = db_to_gain(threshold);
+    let loud = |chunk: &[f32]| {
+        (chunk.iter().map(|x| x * x).sum::<f32>() / chunk.len() as f32).sqrt() >= threshold
+    };
+    let frames: Vec<&[f32]> = samples.chunks(frame).collect();
+    let (Some(first), Some(last)) = (
+        frames.iter().position(|c| loud(c)),
+        frames.iter().rposition(|c| loud(c)),
+    ) else {
+        return 0;
+    };
+    let start = first * frame;
+    let end = ((last + 1) * frame).min(samples.len());
+    samples.truncate(end);
+    samples.drain(..start);
+    start
+}
+
+/// Scale so that the sample peak reaches `target` dBFS
+pub fn normalize_peak(samples: &mut [f32], target: f32) {
+    let peak = samples.iter().fold(0f32, |m, x| m.max(x.abs()));
+    if peak > 0.0 {
+        let gain = db_to_gain(target) / peak;
+        samples.iter_mut().for_each(|x| *x *= gain);
+    }
+}
+
+/// Scale so that the integrated loudness reaches `target` LUFS
+pub fn normalize_loudness(samples: &mut [f32], target: f32, sample_rate: u32) {
+    if let Some(loudness) = integrated_loudness(samples, sample_rate) {
+        let gain = db_to_gain(target - loudness);
+        samples.iter_mut().for_each(|x| *x *= gain);
+    }
+}
+
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Biquad {
+    fn apply(&self, samples: &[f32]) -> Vec<f32> {
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        samples
+            .iter()
+            .map(|&x| {
+                let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2
+                    - self.a[0] * y1
+                    - self.a[1] * y2;
+                (x2, x1, y2, y1) = (x1, x, y1, y);
+                y
+            })
+            .collect()
+    }
+}
+
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f32;
+    // high shelf modelling the acoustic effect of the head
+    let (f0, gain, q) = (1681.9745, 3.999_843_9, 0.707_175_25);
+    let k = (PI * f0 / fs).tan();
+    let vh = 10f32.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_78);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    // RLB high-pass
+    let (f0, q) = (38.135_47, 0.500_327_04);
+    let k = (PI * f0 / fs).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    };
+    [shelf, high_pass]
+}
+
+/// Measure the gated integrated loudness in LUFS
+///
+/// Returns `None` for silent audio.
+pub fn integrated_loudness(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let [shelf, high_pass] = k_weighting(sample_rate);
+    let weighted = high_pass.apply(&shelf.apply(samples));
+    let block = (sample_rate as usize * 4 / 10).min(weighted.len()).max(1);
+    let step = (block / 4).max(1);
+    let mut powers = vec![];
+    let mut start = 0;
+    while start + block <= weighted.len() {
+        let z = weighted[start..start + block]
+            .iter()
+            .map(|x| x * x)
+            .sum::<f32>()
+            / block as f32;
+        powers.push(z);
+        start += step;
+    }
+    let loudness = |z: f32| -0.691 + 10.0 * z.log10();
+    let gated_mean = |threshold: f32| {
+        let gated: Vec<f32> = powers
+            .iter()
+            .copied()
+            .filter(|&z| z > 0.0 && loudness(z) > threshold)
+            .collect();
+        if gated.is_empty() {
+            None
+        } else {
+            Some(gated.iter().sum::<f32>() / gated.len() as f32)
+        }
+    };
+    let relative = loudness(gated_mean(-70.0)?) - 10.0;
+    Some(loudness(gated_mean(relative.max(-70.0))?))
+}
+
+/// Apply linear fade-in and fade-out
+pub fn fade(samples: &mut [f32], fade_in: f32, fade_out: f32, sample_rate: u32) {
+    let len = samples.len();
+    let fade_in = ((fade_in.max(0.0) * sample_rate as f32) as usize).min(len);
+    for (i, x) in samples[..fade_in].iter_mut().enumerate() {
+        *x *= i as f32 / fade_in as f32;
+    }
+    let fade_out = ((fade_out.max(0.0) * sample_rate as f32) as usize).min(len);
+    for (i, x) in samples[len - fade_out..].iter_mut().rev().enumerate() {
+        *x *= i as f32 / fade_out as f32;
+    }
+}
+
+/// Scale the audio down so that no sample exceeds full scale
+pub fn prevent_clipping(samples: &mut [f32]) {
+    let peak = samples.iter().fold(0f32, |m, x| m.max(x.abs()));
+    if peak > 1.0 {
+        samples.iter_mut().for_each(|x| *x /= peak);
+    }
+}
+
+/// Zero crossings of the sinc kernel on each side, at the lower of the two sample rates
+const SINC_ZEROS: f64 = 16.0;
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+    }
+}
+
+/// Blackman window over `-1.0..=1.0`
+fn blackman(x: f64) -> f64 {
+    let x = std::f64::consts::PI * x;
+    0.42 + 0.5 * x.cos() + 0.08 * (2.0 * x).cos()
+}
+
+/// Resample with a windowed-sinc filter
+///
+/// The kernel low-passes slightly below the lower of the two Nyquist frequencies,
+/// so that frequencies the target rate cannot represent are removed instead of folded back.
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    // cutoff relative to the input Nyquist frequency
+    let cutoff = ratio.recip().min(1.0) * 0.95;
+    let half_width = SINC_ZEROS / cutoff;
+    let len = (samples.len() as f64 / ratio).round() as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let first = (position - half_width).ceil().max(0.0) as usize;
+            let last = ((position + half_width).floor() as usize).min(samples.len() - 1);
+            (first..=last)
+                .map(|j| {
+                    let x = position - j as f64;
+                    samples[j] as f64 * cutoff * sinc(cutoff * x) * blackman(x / half_width)
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, seconds: f32, sample_rate: u32) -> Vec<f32> {
+        (0..(seconds * sample_rate as f32) as usize)
+            .map(|i| amplitude * (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// RMS of the middle half, away from the edges of the filter
+    fn rms(samples: &[f32]) -> f32 {
+        let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+        (middle.iter().map(|x| x * x).sum::<f32>() / middle.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn trim_silence_trims_edges() {
+        let mut samples = vec![0.0; 2400];
+        samples.extend(sine(440.0, 0.5, 0.5, 24000));
+        samples.extend(vec![0.0; 4800]);
+        assert_eq!(trim_silence(&mut samples, -40.0, 24000), 2400);
+        assert_eq!(samples.len(), 12000);
+    }
+
+    #[test]
+    fn trim_silence_keeps_silent_audio() {
+        let mut samples = vec![0.0; 24000];
+        assert_eq!(trim_silence(&mut samples, -40.0, 24000), 0);
+        assert_eq!(samples.len(), 24000);
+    }
+
+    #[test]
+    fn normalize_peak_reaches_target() {
+        let mut samples = sine(440.0, 0.25, 0.5, 24000);
+        normalize_peak(&mut samples, -6.0);
+        let peak = samples.iter().fold(0f32, |m, x| m.max(x.abs()));
+        assert!((peak - db_to_gain(-6.0)).abs() < 1e-4);
+        let mut silent = vec![0.0; 100];
+        normalize_peak(&mut silent, -6.0);
+        assert!(silent.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn integrated_loudness_of_reference_sine() {
+        // a 997 Hz sine at -20 dBFS peak measures -23 LUFS in a single channel
+        let samples = sine(997.0, 0.1, 3.0, 48000);
+        let loudness = integrated_loudness(&samples, 48000).unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "{loudness}");
+        assert_eq!(integrated_loudness(&[0.0; 48000], 48000), None);
+    }
+
+    #[test]
+    fn normalize_loudness_reaches_target() {
+        let mut samples = sine(997.0, 0.5, 3.0, 44100);
+        normalize_loudness(&mut samples, -23.0, 44100);
+        let loudness = integrated_loudness(&samples, 44100).unwrap();
+        assert!((loudness + 23.0).abs() < 0.1, "{loudness}");
+        assert!((samples.iter().fold(0f32, |m, x| m.max(x.abs())) - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn fade_endpoints() {
+        let mut samples = vec![1.0; 1000];
+        fade(&mut samples, 0.01, 0.02, 10000);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[50], 0.5);
+        assert_eq!(samples[100], 1.0);
+        assert_eq!(samples[799], 1.0);
+        assert_eq!(samples[899], 0.5);
+        assert_eq!(samples[999], 0.0);
+        // fades longer than the audio are clamped to it
+        let mut samples = vec![1.0; 10];
+        fade(&mut samples, 1.0, 0.0, 10000);
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[9], 0.9);
+    }
+
+    #[test]
+    fn resample_keeps_length_and_passband() {
+        let samples = sine(1000.0, 0.5, 1.0, 44100);
+        let resampled = resample(&samples, 44100, 24000);
+        assert_eq!(resampled.len(), 24000);
+        assert!((rms(&resampled) - rms(&samples)).abs() < 0.01);
+        let upsampled = resample(&samples, 44100, 48000);
+        assert_eq!(upsampled.len(), 48000);
+        assert!((rms(&upsampled) - rms(&samples)).abs() < 0.01);
+    }
+
+    #[test]
+    fn resample_attenuates_above_target_nyquist() {
+        // 6 kHz would fold back to 2 kHz at 8 kHz
+        let samples = sine(6000.0, 0.5, 1.0, 44100);
+        let resampled = resample(&samples, 44100, 8000);
+        assert_eq!(resampled.len(), 8000);
+        assert!(rms(&resampled) < 0.01 * rms(&samples));
+        // 16 kHz would fold back to 8 kHz at 24 kHz
+        let samples = sine(16000.0, 0.5, 1.0, 44100);
+        assert!(rms(&resample(&samples, 44100, 24000)) < 0.01 * rms(&samples));
+    }
+}
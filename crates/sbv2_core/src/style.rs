@@ -1,19 +1,41 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
 use ndarray::{s, Array1, Array2};
 use serde::Deserialize;
 
+/// Style vectors json with the metadata stored next to them
+///
+/// # Fields
+/// - `shape`: Number of styles and style vector size
+/// - `data`: Style vectors
+/// - `names`: Style names indexed by style id, empty if there are none
+/// - `n_speakers`: Number of speakers, `None` for older files
 #[derive(Deserialize)]
-pub struct Data {
+pub struct StyleFile {
     pub shape: [usize; 2],
     pub data: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub names: Vec<String>,
+    pub n_speakers: Option<usize>,
+}
+
+/// Former name of `StyleFile`
+pub type Data = StyleFile;
+
+impl StyleFile {
+    pub fn parse<P: AsRef<[u8]>>(bytes: P) -> Result<Self> {
+        Ok(serde_json::from_slice(bytes.as_ref())?)
+    }
+
+    pub fn style_vectors(&self) -> Result<Array2<f32>> {
+        Ok(Array2::from_shape_vec(
+            self.shape,
+            self.data.iter().flatten().copied().collect(),
+        )?)
+    }
 }
 
 pub fn load_style<P: AsRef<[u8]>>(path: P) -> Result<Array2<f32>> {
-    let data: Data = serde_json::from_slice(path.as_ref())?;
-    Ok(Array2::from_shape_vec(
-        data.shape,
-        data.data.iter().flatten().copied().collect(),
-    )?)
+    StyleFile::parse(path)?.style_vectors()
 }
 
 pub fn get_style_vector(
@@ -21,6 +43,12 @@ pub fn get_style_vector(
     style_id: i32,
     weight: f32,
 ) -> Result<Array1<f32>> {
+    if style_id < 0 || style_id as usize >= style_vectors.nrows() {
+        return Err(Error::ValueError(format!(
+            "Invalid style id {style_id}, the model has {} styles",
+            style_vectors.nrows()
+        )));
+    }
     let mean = style_vectors.slice(s![0, ..]).to_owned();
     let style_vector = style_vectors.slice(s![style_id as usize, ..]).to_owned();
     let diff = (style_vector - &mean) * weight;
@@ -0,0 +1,129 @@
+use serde::Serialize;
+
+/// A subtitle cue aligned to the synthesized audio
+///
+/// # Fields
+/// - `start`: Start time in seconds
+/// - `end`: End time in seconds
+/// - `text`: Text of the sentence
+#[derive(Debug, Clone, Serialize)]
+pub struct SubtitleCue {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+impl SubtitleCue {
+    /// Create a cue from a sample range
+    pub fn from_samples(start: usize, end: usize, sample_rate: u32, text: &str) -> Self {
+        SubtitleCue {
+            start: start as f32 / sample_rate as f32,
+            end: end as f32 / sample_rate as f32,
+            text: text.to_string(),
+        }
+    }
+}
+
+fn format_timestamp(seconds: f32, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (hours, millis) = (millis / 3_600_000, millis % 3_600_000);
+    let (minutes, millis) = (millis / 60_000, millis % 60_000);
+    let (seconds, millis) = (millis / 1000, millis % 1000);
+    format!("{hours:02}:{minutes:02}:{seconds:02}{separator}{millis:03}")
+}
+
+/// Serialize cues as SubRip (.srt)
+///
+/// # Examples
+///
+/// ```rs
+/// std::fs::write("output.srt", subtitle::to_srt(&cues))?;
+/// ```
+pub fn to_srt(cues: &[SubtitleCue]) -> String {
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ','),
+            cue.text
+        ));
+    }
+    srt
+}
+
+/// Serialize cues as WebVTT (.vtt)
+///
+/// # Examples
+///
+/// ```rs
+/// std::fs::write("output.vtt", subtitle::to_vtt(&cues))?;
+/// ```
+pub fn to_vtt(cues: &[SubtitleCue]) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    for cue in cues {
+        vtt.push_str(&format!(
+            "{} --> {}\n{}\n\n",
+            format_timestamp(cue.start, '.'),
+            format_timestamp(cue.end, '.'),
+            cue.text
+        ));
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cue(start: f32, end: f32, text: &str) -> SubtitleCue {
+        SubtitleCue {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn format_timestamp_rolls_over() {
+        assert_eq!(format_timestamp(0.0, ','), "00:00:00,000");
+        assert_eq!(format_timestamp(59.999, ','), "00:00:59,999");
+        assert_eq!(format_timestamp(60.0, ','), "00:01:00,000");
+        assert_eq!(format_timestamp(3599.0, ','), "00:59:59,000");
+        assert_eq!(format_timestamp(3600.0, ','), "01:00:00,000");
+        assert_eq!(format_timestamp(3723.5, ','), "01:02:03,500");
+        assert_eq!(format_timestamp(-1.0, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn format_timestamp_rounds_milliseconds() {
+        assert_eq!(format_timestamp(1.0004, '.'), "00:00:01.000");
+        assert_eq!(format_timestamp(1.0006, '.'), "00:00:01.001");
+        // rounding up carries into the seconds, minutes and hours
+        assert_eq!(format_timestamp(59.9996, '.'), "00:01:00.000");
+        assert_eq!(format_timestamp(3599.9998, '.'), "01:00:00.000");
+        let cue = SubtitleCue::from_samples(12345, 36000, 24000, "");
+        assert_eq!(format_timestamp(cue.start, '.'), "00:00:00.514");
+        assert_eq!(format_timestamp(cue.end, '.'), "00:00:01.500");
+    }
+
+    #[test]
+    fn srt_and_vtt_separators() {
+        let cues = vec![
+            cue(0.0, 1.25, "こんにちは。"),
+            cue(1.5, 3661.0, "元気ですか？"),
+        ];
+        assert_eq!(
+            to_srt(&cues),
+            "1\n00:00:00,000 --> 00:00:01,250\nこんにちは。\n\n\
+             2\n00:00:01,500 --> 01:01:01,000\n元気ですか？\n\n"
+        );
+        assert_eq!(
+            to_vtt(&cues),
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:01.250\nこんにちは。\n\n\
+             00:00:01.500 --> 01:01:01.000\n元気ですか？\n\n"
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
 pub use tokenizers::Tokenizer;
 
 pub fn get_tokenizer<P: AsRef<[u8]>>(p: P) -> Result<Tokenizer> {
@@ -6,16 +6,178 @@ pub fn get_tokenizer<P: AsRef<[u8]>>(p: P) -> Result<Tokenizer> {
     Ok(tokenizer)
 }
 
-pub fn tokenize(text: &str, tokenizer: &Tokenizer) -> Result<(Vec<i64>, Vec<i64>)> {
-    let mut token_ids = vec![1];
-    let mut attention_masks = vec![1];
-    for content in text.chars() {
-        let token = tokenizer.encode(content.to_string(), false)?;
-        let ids = token.get_ids();
-        token_ids.extend(ids.iter().map(|&x| x as i64));
-        attention_masks.extend(token.get_attention_mask().iter().map(|&x| x as i64));
-    }
-    token_ids.push(2);
-    attention_masks.push(1);
-    Ok((token_ids, attention_masks))
+fn special_token_id(tokenizer: &Tokenizer, token: &str) -> Result<i64> {
+    tokenizer
+        .token_to_id(token)
+        .map(|id| id as i64)
+        .ok_or_else(|| Error::ValueError(format!("Tokenizer has no {token} token")))
+}
+
+/// Tokenize text in one pass
+///
+/// Returns the token ids, the attention mask and the index of the token each character
+/// belongs to, with the special tokens at both ends.
+pub fn tokenize(text: &str, tokenizer: &Tokenizer) -> Result<(Vec<i64>, Vec<i64>, Vec<usize>)> {
+    let encoding = tokenizer.encode_char_offsets(text, true)?;
+    let special = encoding.get_special_tokens_mask();
+    let mut token_ids: Vec<i64> = encoding.get_ids().iter().map(|&x| x as i64).collect();
+    let mut attention_masks: Vec<i64> = encoding
+        .get_attention_mask()
+        .iter()
+        .map(|&x| x as i64)
+        .collect();
+    // tokenizers without a post-processor do not add the special tokens themselves
+    let shift = if special.first() == Some(&1) {
+        0
+    } else {
+        token_ids.insert(0, special_token_id(tokenizer, "[CLS]")?);
+        attention_masks.insert(0, 1);
+        1
+    };
+    if special.last() != Some(&1) {
+        token_ids.push(special_token_id(tokenizer, "[SEP]")?);
+        attention_masks.push(1);
+    }
+
+    let mut char_to_token = vec![0];
+    let mut tokens = encoding
+        .get_offsets()
+        .iter()
+        .zip(special)
+        .enumerate()
+        .filter(|(_, (_, &special))| special == 0)
+        .map(|(i, (offset, _))| (i + shift, *offset))
+        .peekable();
+    let mut current = 0;
+    for c in 0..text.chars().count() {
+        while let Some(&(i, (start, end))) = tokens.peek() {
+            if c < start {
+                break;
+            }
+            tokens.next();
+            current = i;
+            if c < end {
+                break;
+            }
+        }
+        char_to_token.push(current);
+    }
+    char_to_token.push(token_ids.len() - 1);
+    Ok((token_ids, attention_masks, char_to_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn special_tokens(first_id: u32) -> Value {
+        json!([
+            {"id": first_id, "content": "[CLS]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true},
+            {"id": first_id + 1, "content": "[SEP]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true},
+        ])
+    }
+
+    fn build(
+        model: Value,
+        pre_tokenizer: Value,
+        added_tokens: Value,
+        post_processor: bool,
+    ) -> Tokenizer {
+        let cls = added_tokens[0]["id"].clone();
+        let sep = added_tokens[1]["id"].clone();
+        let post_processor = if post_processor {
+            json!({"type": "BertProcessing", "cls": ["[CLS]", cls], "sep": ["[SEP]", sep]})
+        } else {
+            Value::Null
+        };
+        let json = json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": added_tokens,
+            "normalizer": null,
+            "pre_tokenizer": pre_tokenizer,
+            "post_processor": post_processor,
+            "decoder": null,
+            "model": model,
+        });
+        get_tokenizer(json.to_string()).unwrap()
+    }
+
+    /// WordPiece tokenizer where `今日` is a single token
+    fn word_piece(post_processor: bool) -> Tokenizer {
+        build(
+            json!({
+                "type": "WordPiece",
+                "unk_token": "[UNK]",
+                "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100,
+                "vocab": {"[UNK]": 0, "今日": 1, "##は": 2, "##晴": 3, "##れ": 4, "。": 5},
+            }),
+            json!({"type": "Whitespace"}),
+            special_tokens(6),
+            post_processor,
+        )
+    }
+
+    /// Byte-level BPE tokenizer where `日` is split into two tokens and `本` into three
+    fn byte_level(post_processor: bool) -> Tokenizer {
+        build(
+            json!({
+                "type": "BPE",
+                "dropout": null,
+                "unk_token": null,
+                "continuing_subword_prefix": null,
+                "end_of_word_suffix": null,
+                "fuse_unk": false,
+                "byte_fallback": false,
+                "ignore_merges": false,
+                "vocab": {"æ": 0, "Ĺ": 1, "¥": 2, "ľ": 3, "¬": 4, "æĹ": 5},
+                "merges": ["æ Ĺ"],
+            }),
+            json!({"type": "ByteLevel", "add_prefix_space": false, "trim_offsets": false, "use_regex": true}),
+            special_tokens(6),
+            post_processor,
+        )
+    }
+
+    #[test]
+    fn characters_sharing_a_token() {
+        for post_processor in [false, true] {
+            let (token_ids, attention_masks, char_to_token) =
+                tokenize("今日は晴れ。", &word_piece(post_processor)).unwrap();
+            assert_eq!(token_ids, vec![6, 1, 2, 3, 4, 5, 7]);
+            assert_eq!(attention_masks, vec![1; 7]);
+            assert_eq!(char_to_token, vec![0, 1, 1, 2, 3, 4, 5, 6]);
+        }
+    }
+
+    #[test]
+    fn character_split_into_several_tokens() {
+        for post_processor in [false, true] {
+            let (token_ids, attention_masks, char_to_token) =
+                tokenize("日本", &byte_level(post_processor)).unwrap();
+            assert_eq!(token_ids, vec![6, 5, 2, 0, 3, 4, 7]);
+            assert_eq!(attention_masks, vec![1; 7]);
+            assert_eq!(char_to_token, vec![0, 1, 3, 6]);
+        }
+    }
+
+    #[test]
+    fn missing_special_tokens() {
+        let tokenizer = build(
+            json!({
+                "type": "WordPiece",
+                "unk_token": "[UNK]",
+                "continuing_subword_prefix": "##",
+                "max_input_chars_per_word": 100,
+                "vocab": {"[UNK]": 0, "今日": 1},
+            }),
+            json!({"type": "Whitespace"}),
+            json!([]),
+            false,
+        );
+        assert!(tokenize("今日", &tokenizer).is_err());
+    }
 }
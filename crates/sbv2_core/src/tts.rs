@@ -1,13 +1,18 @@
 use crate::error::{Error, Result};
-use crate::{jtalk, model, style, tokenizer, tts_util};
+use crate::model::{SessionConfig, SessionOptions};
+use crate::postprocess::{self, PostProcessOptions};
+use crate::subtitle::SubtitleCue;
+use crate::viseme::{self, PhonemeTiming};
+use crate::{bert, jtalk, model, norm, pitch, style, tokenizer, tts_util};
 #[cfg(feature = "aivmx")]
 use base64::prelude::{Engine as _, BASE64_STANDARD};
 #[cfg(feature = "aivmx")]
 use ndarray::ShapeBuilder;
-use ndarray::{concatenate, Array1, Array2, Array3, Axis};
+use ndarray::{Array1, Array2, Array3};
 use ort::session::Session;
 #[cfg(feature = "aivmx")]
 use std::io::Cursor;
+use std::time::SystemTime;
 use tokenizers::Tokenizer;
 
 #[derive(PartialEq, Eq, Clone)]
@@ -31,11 +36,196 @@ where
 
 pub struct TTSModel {
     vits2: Option<Session>,
+    pool: Vec<Session>,
+    options: SessionOptions,
     style_vectors: Array2<f32>,
+    style_names: Vec<String>,
+    speaker_count: Option<usize>,
+    last_used: Option<SystemTime>,
     ident: TTSIdent,
     bytes: Option<Vec<u8>>,
 }
 
+/// Metadata of a model held by `TTSModelHolder`
+///
+/// # Fields
+/// - `ident`: Model name
+/// - `styles`: Style names indexed by style id, the id itself if the model has no names
+/// - `speaker_count`: Number of speakers, `None` if the model does not record it
+/// - `sample_rate`: Sample rate of the synthesized audio
+/// - `loaded`: Whether the VITS2 session is in memory
+/// - `last_used`: When the model was last used for synthesis
+#[derive(Debug, Clone)]
+pub struct ModelInfo {
+    pub ident: String,
+    pub styles: Vec<String>,
+    pub speaker_count: Option<usize>,
+    pub sample_rate: u32,
+    pub loaded: bool,
+    pub last_used: Option<SystemTime>,
+}
+
+impl TTSModel {
+    fn info(&self) -> ModelInfo {
+        ModelInfo {
+            ident: self.ident.to_string(),
+            styles: (0..self.style_vectors.nrows())
+                .map(|i| {
+                    self.style_names
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| i.to_string())
+                })
+                .collect(),
+            speaker_count: self.speaker_count,
+            sample_rate: tts_util::SAMPLE_RATE,
+            loaded: self.vits2.is_some(),
+            last_used: self.last_used,
+        }
+    }
+}
+
+/// Builds models without access to a `TTSModelHolder`
+///
+/// Creating the VITS session is the slow part of loading a model. A loader taken with
+/// `TTSModelHolder::loader` does it on its own, so that a holder shared behind a lock only
+/// has to be locked again to `insert` the result.
+#[derive(Debug, Clone)]
+pub struct ModelLoader {
+    options: SessionOptions,
+    parallel_sentences: usize,
+    keep_bytes: bool,
+    load_session: bool,
+}
+
+impl ModelLoader {
+    /// VITS session options with `intra_threads` divided between the parallel workers
+    fn worker_options(&self, mut options: SessionOptions) -> SessionOptions {
+        options.intra_threads = (options.intra_threads / self.parallel_sentences).max(1);
+        options
+    }
+
+    #[cfg(feature = "aivmx")]
+    pub fn load_aivmx<I: Into<TTSIdent>, P: AsRef<[u8]>>(
+        &self,
+        ident: I,
+        aivmx_bytes: P,
+    ) -> Result<TTSModel> {
+        let ident = ident.into();
+        let options = self.worker_options(self.options.clone());
+        let model = model::load_model(&aivmx_bytes, false, &options)?;
+        let metadata = model.metadata()?;
+        let aivm_style_vectors = metadata.custom("aivm_style_vectors")?.ok_or_else(|| {
+            Error::ValueError(format!("{ident} has no aivm_style_vectors metadata"))
+        })?;
+        let aivm_style_vectors = BASE64_STANDARD.decode(aivm_style_vectors)?;
+        let style_vectors = Cursor::new(&aivm_style_vectors);
+        let reader = npyz::NpyFile::new(style_vectors)?;
+        let style_vectors = {
+            let shape = reader.shape().to_vec();
+            let order = reader.order();
+            let data = reader.into_vec::<f32>()?;
+            let shape = match shape[..] {
+                [i1, i2] => [i1 as usize, i2 as usize],
+                _ => panic!("expected 2D array"),
+            };
+            let true_shape = shape.set_f(order == npyz::Order::Fortran);
+            ndarray::Array2::from_shape_vec(true_shape, data)?
+        };
+        let hyper_parameters = metadata
+            .custom("aivm_hyper_parameters")?
+            .and_then(|h| serde_json::from_str::<serde_json::Value>(&h).ok())
+            .unwrap_or_default();
+        let speaker_count = hyper_parameters["data"]["n_speakers"]
+            .as_u64()
+            .map(|n| n as usize);
+        let mut style_names = vec![String::new(); style_vectors.nrows()];
+        if let Some(style2id) = hyper_parameters["data"]["style2id"].as_object() {
+            for (name, id) in style2id {
+                if let Some(slot) = id.as_u64().and_then(|id| style_names.get_mut(id as usize)) {
+                    *slot = name.clone();
+                }
+            }
+        }
+        // Fall back to ids unless every style is named
+        if style_names.iter().any(|n| n.is_empty()) {
+            style_names.clear();
+        }
+        drop(metadata);
+        Ok(TTSModel {
+            pool: vec![],
+            options,
+            vits2: if self.load_session { Some(model) } else { None },
+            bytes: if self.keep_bytes {
+                Some(aivmx_bytes.as_ref().to_vec())
+            } else {
+                None
+            },
+            ident,
+            style_vectors,
+            style_names,
+            speaker_count,
+            last_used: None,
+        })
+    }
+
+    /// Build a model from a .sbv2 file binary
+    pub fn load_sbv2file<I: Into<TTSIdent>, P: AsRef<[u8]>>(
+        &self,
+        ident: I,
+        sbv2_bytes: P,
+    ) -> Result<TTSModel> {
+        let (style_vectors, vits2) = crate::sbv2file::parse_sbv2file(sbv2_bytes)?;
+        self.load(ident, style_vectors, vits2)
+    }
+
+    /// Build a model from a style vector and onnx model binary
+    pub fn load<I: Into<TTSIdent>, P: AsRef<[u8]>>(
+        &self,
+        ident: I,
+        style_vectors_bytes: P,
+        vits2_bytes: P,
+    ) -> Result<TTSModel> {
+        self.load_with_options(
+            ident,
+            style_vectors_bytes,
+            vits2_bytes,
+            self.options.clone(),
+        )
+    }
+
+    /// Build a model from a style vector and onnx model binary with its own session options
+    pub fn load_with_options<I: Into<TTSIdent>, P: AsRef<[u8]>>(
+        &self,
+        ident: I,
+        style_vectors_bytes: P,
+        vits2_bytes: P,
+        options: SessionOptions,
+    ) -> Result<TTSModel> {
+        let options = self.worker_options(options);
+        let style_file = style::StyleFile::parse(&style_vectors_bytes)?;
+        Ok(TTSModel {
+            pool: vec![],
+            vits2: if self.load_session {
+                Some(model::load_model(&vits2_bytes, false, &options)?)
+            } else {
+                None
+            },
+            options,
+            style_vectors: style_file.style_vectors()?,
+            style_names: style_file.names,
+            speaker_count: style_file.n_speakers,
+            last_used: None,
+            ident: ident.into(),
+            bytes: if self.keep_bytes {
+                Some(vits2_bytes.as_ref().to_vec())
+            } else {
+                None
+            },
+        })
+    }
+}
+
 /// High-level Style-Bert-VITS2's API
 pub struct TTSModelHolder {
     tokenizer: Tokenizer,
@@ -43,6 +233,11 @@ pub struct TTSModelHolder {
     models: Vec<TTSModel>,
     jtalk: jtalk::JTalk,
     max_loaded_models: Option<usize>,
+    /// Whether the BERT model accepts batches, `None` until the first batch is run
+    bert_batch: Option<bool>,
+    session_config: SessionConfig,
+    warmup: bool,
+    parallel_sentences: usize,
 }
 
 impl TTSModelHolder {
@@ -58,7 +253,30 @@ impl TTSModelHolder {
         tokenizer_bytes: P,
         max_loaded_models: Option<usize>,
     ) -> Result<Self> {
-        let bert = model::load_model(bert_model_bytes, true)?;
+        Self::with_session_config(
+            bert_model_bytes,
+            tokenizer_bytes,
+            max_loaded_models,
+            SessionConfig::default(),
+        )
+    }
+
+    /// Initialize a new TTSModelHolder with the options of the BERT and VITS sessions
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let mut config = SessionConfig::default();
+    /// config.vits.intra_threads = 2;
+    /// let mut tts_holder = TTSModelHolder::with_session_config(std::fs::read("deberta.onnx")?, std::fs::read("tokenizer.json")?, None, config)?;
+    /// ```
+    pub fn with_session_config<P: AsRef<[u8]>>(
+        bert_model_bytes: P,
+        tokenizer_bytes: P,
+        max_loaded_models: Option<usize>,
+        session_config: SessionConfig,
+    ) -> Result<Self> {
+        let bert = model::load_model(bert_model_bytes, true, &session_config.bert)?;
         let jtalk = jtalk::JTalk::new()?;
         let tokenizer = tokenizer::get_tokenizer(tokenizer_bytes)?;
         Ok(TTSModelHolder {
@@ -67,14 +285,166 @@ impl TTSModelHolder {
             jtalk,
             tokenizer,
             max_loaded_models,
+            bert_batch: None,
+            session_config,
+            warmup: false,
+            parallel_sentences: 1,
         })
     }
 
+    /// Run a short synthesis whenever a model is loaded with one of the `load*` methods
+    ///
+    /// This moves session initialization and allocator warmup out of the first request.
+    /// Models reloaded on demand after being unloaded are not warmed up, as the request
+    /// waiting for them would pay for it.
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// tts_holder.set_warmup(true);
+    /// ```
+    pub fn set_warmup(&mut self, warmup: bool) {
+        self.warmup = warmup;
+    }
+
+    /// Keep up to `workers` VITS sessions per model for `SynthesizeOptions::parallel_sentences`
+    ///
+    /// The model bytes are kept in memory to create the extra sessions, and the
+    /// `intra_threads` of every VITS session is divided between the workers so that they
+    /// do not oversubscribe the cores together. Only models loaded after this call are affected.
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// tts_holder.set_parallel_sentences(4);
+    /// ```
+    pub fn set_parallel_sentences(&mut self, workers: usize) {
+        self.parallel_sentences = workers.max(1);
+    }
+
+    /// Number of models whose VITS session is in memory
+    fn loaded_count(&self) -> usize {
+        self.models.iter().filter(|x| x.vits2.is_some()).count()
+    }
+
+    /// Return a loader that builds models with this holder's settings
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let loader = tts_holder.loader();
+    /// let model = loader.load_sbv2file("tsukuyomi", std::fs::read("tsukuyomi.sbv2")?)?;
+    /// tts_holder.insert(model);
+    /// ```
+    pub fn loader(&self) -> ModelLoader {
+        ModelLoader {
+            options: self.session_config.vits.clone(),
+            parallel_sentences: self.parallel_sentences,
+            keep_bytes: self.max_loaded_models.is_some() || self.parallel_sentences > 1,
+            load_session: self
+                .max_loaded_models
+                .is_none_or(|max| self.loaded_count() < max),
+        }
+    }
+
+    /// Add a model built by a `ModelLoader`
+    ///
+    /// Returns `false` and drops the model if one of the same name is already held.
+    /// The session is dropped too if `max_loaded_models` was reached since the loader was taken.
+    pub fn insert(&mut self, mut model: TTSModel) -> bool {
+        if self.models.iter().any(|m| m.ident == model.ident) {
+            return false;
+        }
+        if let Some(max) = self.max_loaded_models {
+            if model.vits2.is_some() && self.loaded_count() >= max {
+                model.vits2 = None;
+            }
+        }
+        let ident = model.ident.clone();
+        let loaded = model.vits2.is_some();
+        self.models.push(model);
+        if loaded {
+            self.warmup_model(&ident);
+        }
+        true
+    }
+
+    fn warmup_model(&mut self, ident: &TTSIdent) {
+        if !self.warmup {
+            return;
+        }
+        let start = std::time::Instant::now();
+        let result = (|| -> Result<()> {
+            let parsed = self.parse_text("こんにちは。")?;
+            let style_vector = self.get_style_vector(ident.clone(), 0, 1.0)?;
+            self.synthesize_sentence(
+                ident,
+                parsed,
+                style_vector,
+                0,
+                &SynthesizeOptions::default(),
+            )?;
+            Ok(())
+        })();
+        match result {
+            Ok(()) => log::info!("Warmed up {ident} in {:?}", start.elapsed()),
+            Err(e) => log::warn!("Failed to warm up {ident}: {e}"),
+        }
+    }
+
     /// Return a list of model names
     pub fn models(&self) -> Vec<String> {
         self.models.iter().map(|m| m.ident.to_string()).collect()
     }
 
+    /// Return the metadata of a model
+    pub fn model_info<I: Into<TTSIdent>>(&self, ident: I) -> Result<ModelInfo> {
+        let ident = ident.into();
+        self.models
+            .iter()
+            .find(|m| m.ident == ident)
+            .map(|m| m.info())
+            .ok_or(Error::ModelNotFoundError(ident.to_string()))
+    }
+
+    /// Return the metadata of every model
+    pub fn models_info(&self) -> Vec<ModelInfo> {
+        self.models.iter().map(|m| m.info()).collect()
+    }
+
+    /// Return the number of styles of a model
+    pub fn style_count<I: Into<TTSIdent>>(&self, ident: I) -> Result<usize> {
+        let ident = ident.into();
+        self.models
+            .iter()
+            .find(|m| m.ident == ident)
+            .map(|m| m.style_vectors.nrows())
+            .ok_or(Error::ModelNotFoundError(ident.to_string()))
+    }
+
+    /// Return the number of speakers of a model, `None` if the model does not record it
+    pub fn speaker_count<I: Into<TTSIdent>>(&self, ident: I) -> Result<Option<usize>> {
+        let ident = ident.into();
+        self.models
+            .iter()
+            .find(|m| m.ident == ident)
+            .map(|m| m.speaker_count)
+            .ok_or(Error::ModelNotFoundError(ident.to_string()))
+    }
+
+    /// Split text into accent phrases
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let phrases = tts_holder.accent_phrases("こんにちは、世界")?;
+    /// ```
+    pub fn accent_phrases(&self, text: &str) -> Result<Vec<jtalk::AccentPhrase>> {
+        let text = self.jtalk.num2word(text)?;
+        let normalized_text = norm::normalize_text(&text);
+        self.jtalk.process_text(&normalized_text)?.accent_phrases()
+    }
+
     #[cfg(feature = "aivmx")]
     pub fn load_aivmx<I: Into<TTSIdent>, P: AsRef<[u8]>>(
         &mut self,
@@ -83,41 +453,8 @@ impl TTSModelHolder {
     ) -> Result<()> {
         let ident = ident.into();
         if self.find_model(ident.clone()).is_err() {
-            let mut load = true;
-            if let Some(max) = self.max_loaded_models {
-                if self.models.iter().filter(|x| x.vits2.is_some()).count() >= max {
-                    load = false;
-                }
-            }
-            let model = model::load_model(&aivmx_bytes, false)?;
-            let metadata = model.metadata()?;
-            if let Some(aivm_style_vectors) = metadata.custom("aivm_style_vectors")? {
-                let aivm_style_vectors = BASE64_STANDARD.decode(aivm_style_vectors)?;
-                let style_vectors = Cursor::new(&aivm_style_vectors);
-                let reader = npyz::NpyFile::new(style_vectors)?;
-                let style_vectors = {
-                    let shape = reader.shape().to_vec();
-                    let order = reader.order();
-                    let data = reader.into_vec::<f32>()?;
-                    let shape = match shape[..] {
-                        [i1, i2] => [i1 as usize, i2 as usize],
-                        _ => panic!("expected 2D array"),
-                    };
-                    let true_shape = shape.set_f(order == npyz::Order::Fortran);
-                    ndarray::Array2::from_shape_vec(true_shape, data)?
-                };
-                drop(metadata);
-                self.models.push(TTSModel {
-                    vits2: if load { Some(model) } else { None },
-                    bytes: if self.max_loaded_models.is_some() {
-                        Some(aivmx_bytes.as_ref().to_vec())
-                    } else {
-                        None
-                    },
-                    ident,
-                    style_vectors,
-                })
-            }
+            let model = self.loader().load_aivmx(ident, aivmx_bytes)?;
+            self.insert(model);
         }
         Ok(())
     }
@@ -151,29 +488,35 @@ impl TTSModelHolder {
         ident: I,
         style_vectors_bytes: P,
         vits2_bytes: P,
+    ) -> Result<()> {
+        let options = self.session_config.vits.clone();
+        self.load_with_options(ident, style_vectors_bytes, vits2_bytes, options)
+    }
+
+    /// Load a style vector and onnx model binary with its own session options
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let options = SessionOptions { intra_threads: 2, ..Default::default() };
+    /// tts_holder.load_with_options("tsukuyomi", std::fs::read("style_vectors.json")?, std::fs::read("model.onnx")?, options)?;
+    /// ```
+    pub fn load_with_options<I: Into<TTSIdent>, P: AsRef<[u8]>>(
+        &mut self,
+        ident: I,
+        style_vectors_bytes: P,
+        vits2_bytes: P,
+        options: SessionOptions,
     ) -> Result<()> {
         let ident = ident.into();
         if self.find_model(ident.clone()).is_err() {
-            let mut load = true;
-            if let Some(max) = self.max_loaded_models {
-                if self.models.iter().filter(|x| x.vits2.is_some()).count() >= max {
-                    load = false;
-                }
-            }
-            self.models.push(TTSModel {
-                vits2: if load {
-                    Some(model::load_model(&vits2_bytes, false)?)
-                } else {
-                    None
-                },
-                style_vectors: style::load_style(style_vectors_bytes)?,
+            let model = self.loader().load_with_options(
                 ident,
-                bytes: if self.max_loaded_models.is_some() {
-                    Some(vits2_bytes.as_ref().to_vec())
-                } else {
-                    None
-                },
-            })
+                style_vectors_bytes,
+                vits2_bytes,
+                options,
+            )?;
+            self.insert(model);
         }
         Ok(())
     }
@@ -213,6 +556,59 @@ impl TTSModelHolder {
         )
     }
 
+    /// Parse several texts, running BERT on them in batches
+    ///
+    /// Falls back to one session call per text if the BERT model does not support batching.
+    #[allow(clippy::type_complexity)]
+    pub fn parse_texts(
+        &mut self,
+        texts: &[&str],
+    ) -> Result<Vec<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)>> {
+        let mut parsed = vec![];
+        for chunk in texts.chunks(bert::MAX_BATCH_SIZE) {
+            parsed.extend(tts_util::parse_texts_blocking(
+                chunk,
+                &self.jtalk,
+                &self.tokenizer,
+                |inputs| {
+                    let mut probe_error = None;
+                    if self.bert_batch != Some(false) && inputs.len() > 1 {
+                        // models with a fixed batch axis fail or drop the batch axis
+                        match bert::predict_batch(&mut self.bert, &inputs) {
+                            Ok(Some(outputs)) => {
+                                self.bert_batch = Some(true);
+                                return Ok(outputs);
+                            }
+                            Ok(None) => {
+                                log::info!(
+                                    "The BERT model drops the batch axis, disabling batching"
+                                );
+                                self.bert_batch = Some(false);
+                            }
+                            Err(e) if self.bert_batch.is_none() => probe_error = Some(e),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    let outputs = inputs
+                        .into_iter()
+                        .map(|(token_ids, attention_masks)| {
+                            bert::predict(&mut self.bert, token_ids, attention_masks)
+                        })
+                        .collect::<Result<Vec<_>>>();
+                    // only a batch that fails where single sentences succeed is a mismatch
+                    if let (Some(e), Ok(_)) = (probe_error, &outputs) {
+                        log::info!(
+                            "The BERT model does not accept batches, disabling batching: {e}"
+                        );
+                        self.bert_batch = Some(false);
+                    }
+                    outputs
+                },
+            )?);
+        }
+        Ok(parsed)
+    }
+
     fn find_model<I: Into<TTSIdent>>(&mut self, ident: I) -> Result<&mut TTSModel> {
         let ident = ident.into();
         self.models
@@ -222,28 +618,40 @@ impl TTSModelHolder {
     }
     fn find_and_load_model<I: Into<TTSIdent>>(&mut self, ident: I) -> Result<bool> {
         let ident = ident.into();
-        let (bytes, style_vectors) = {
+        let (bytes, style_vectors, style_names, speaker_count, options) = {
             let model = self
                 .models
-                .iter()
+                .iter_mut()
                 .find(|m| m.ident == ident)
                 .ok_or(Error::ModelNotFoundError(ident.to_string()))?;
+            model.last_used = Some(SystemTime::now());
             if model.vits2.is_some() {
                 return Ok(true);
             }
-            (model.bytes.clone().unwrap(), model.style_vectors.clone())
+            (
+                model.bytes.clone().unwrap(),
+                model.style_vectors.clone(),
+                model.style_names.clone(),
+                model.speaker_count,
+                model.options.clone(),
+            )
         };
         self.unload(ident.clone());
-        let s = model::load_model(&bytes, false)?;
+        let s = model::load_model(&bytes, false, &options)?;
         if let Some(max) = self.max_loaded_models {
             if self.models.iter().filter(|x| x.vits2.is_some()).count() >= max {
                 self.unload(self.models.first().unwrap().ident.clone());
             }
         }
         self.models.push(TTSModel {
+            pool: vec![],
+            options,
             bytes: Some(bytes.to_vec()),
             vits2: Some(s),
             style_vectors,
+            style_names,
+            speaker_count,
+            last_used: Some(SystemTime::now()),
             ident: ident.clone(),
         });
         let model = self
@@ -270,6 +678,117 @@ impl TTSModelHolder {
         style::get_style_vector(&self.find_model(ident)?.style_vectors, style_id, weight)
     }
 
+    #[allow(clippy::type_complexity)]
+    fn synthesize_sentence(
+        &mut self,
+        ident: &TTSIdent,
+        parsed: (Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>),
+        style_vector: Array1<f32>,
+        speaker_id: i64,
+        options: &SynthesizeOptions,
+    ) -> Result<(Array3<f32>, Vec<PhonemeTiming>)> {
+        let vits2 = self
+            .find_model(ident.clone())?
+            .vits2
+            .as_mut()
+            .ok_or(Error::ModelNotFoundError(ident.to_string()))?;
+        synthesize_parsed(vits2, parsed, style_vector, speaker_id, options)
+    }
+
+    /// Synthesize sentences concurrently, each worker with its own VITS session
+    ///
+    /// BERT runs on the shared session one sentence at a time while the other workers
+    /// do G2P or VITS inference.
+    fn synthesize_parallel(
+        &mut self,
+        ident: &TTSIdent,
+        texts: &[&str],
+        style_vector: &Array1<f32>,
+        speaker_id: i64,
+        options: &SynthesizeOptions,
+    ) -> Result<Vec<(Array3<f32>, Vec<PhonemeTiming>)>> {
+        let workers = options
+            .parallel_sentences
+            .min(self.parallel_sentences)
+            .min(texts.len())
+            .max(1);
+        let model = self.find_model(ident.clone())?;
+        while model.pool.len() + 1 < workers {
+            // models loaded before `set_parallel_sentences` do not keep their bytes
+            let Some(bytes) = model.bytes.as_ref() else {
+                break;
+            };
+            model
+                .pool
+                .push(model::load_model(bytes, false, &model.options)?);
+        }
+        let workers = workers.min(model.pool.len() + 1);
+
+        let TTSModelHolder {
+            tokenizer,
+            bert,
+            models,
+            jtalk,
+            ..
+        } = self;
+        let model = models
+            .iter_mut()
+            .find(|m| &m.ident == ident)
+            .ok_or(Error::ModelNotFoundError(ident.to_string()))?;
+        let sessions = model
+            .vits2
+            .iter_mut()
+            .chain(model.pool.iter_mut())
+            .take(workers);
+        let bert = std::sync::Mutex::new(bert);
+        let (jtalk, tokenizer, bert) = (&*jtalk, &*tokenizer, &bert);
+
+        let mut results = std::thread::scope(|scope| {
+            let handles: Vec<_> = sessions
+                .enumerate()
+                .map(|(worker, vits2)| {
+                    scope.spawn(move || -> Result<Vec<_>> {
+                        let mut results = vec![];
+                        for i in (worker..texts.len()).step_by(workers) {
+                            let mut prepared = tts_util::prepare_text(texts[i], jtalk, tokenizer)?;
+                            let bert_content = {
+                                let mut bert = bert.lock().map_err(|_| {
+                                    Error::OtherError("BERT session is poisoned".to_string())
+                                })?;
+                                bert::predict(
+                                    &mut bert,
+                                    std::mem::take(&mut prepared.token_ids),
+                                    std::mem::take(&mut prepared.attention_masks),
+                                )?
+                            };
+                            let parsed = prepared.finish(bert_content)?;
+                            let output = synthesize_parsed(
+                                vits2,
+                                parsed,
+                                style_vector.clone(),
+                                speaker_id,
+                                options,
+                            )?;
+                            results.push((i, output));
+                        }
+                        Ok(results)
+                    })
+                })
+                .collect();
+            let mut results = vec![];
+            for handle in handles {
+                results.extend(
+                    handle.join().map_err(|_| {
+                        Error::OtherError("Synthesis thread panicked".to_string())
+                    })??,
+                );
+            }
+            Ok::<_, Error>(results)
+        })?;
+        results.sort_by_key(|(i, _)| *i);
+        Ok(results.into_iter().map(|(_, output)| output).collect())
+    }
+
     /// Synthesize text to audio
     ///
     /// # Examples
@@ -285,68 +804,289 @@ impl TTSModelHolder {
         speaker_id: i64,
         options: SynthesizeOptions,
     ) -> Result<Vec<u8>> {
-        self.find_and_load_model(ident)?;
-        let style_vector = self.get_style_vector(ident, style_id, options.style_weight)?;
-        let audio_array = if options.split_sentences {
-            let texts: Vec<&str> = text.split('\n').collect();
-            let mut audios = vec![];
-            for (i, t) in texts.iter().enumerate() {
-                if t.is_empty() {
-                    continue;
-                }
-                let (bert_ori, phones, tones, lang_ids) = self.parse_text(t)?;
-
-                let vits2 = self
-                    .find_model(ident)?
-                    .vits2
-                    .as_mut()
-                    .ok_or(Error::ModelNotFoundError(ident.into().to_string()))?;
-                let audio = model::synthesize(
-                    vits2,
-                    bert_ori.to_owned(),
-                    phones,
-                    Array1::from_vec(vec![speaker_id]),
-                    tones,
-                    lang_ids,
-                    style_vector.clone(),
-                    options.sdp_ratio,
-                    options.length_scale,
-                    0.677,
-                    0.8,
-                )?;
-                audios.push(audio.clone());
-                if i != texts.len() - 1 {
-                    audios.push(Array3::zeros((1, 1, 22050)));
+        let output = self.easy_synthesize_detailed(ident, text, style_id, speaker_id, options)?;
+        tts_util::array_to_vec(output.audio)
+    }
+
+    /// Synthesize text to raw samples and return them with the sample rate
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let (samples, sample_rate) = tts_holder.synthesize_samples("tsukuyomi", "こんにちは", 0, 0, SynthesizeOptions::default())?;
+    /// ```
+    pub fn synthesize_samples<I: Into<TTSIdent> + Copy>(
+        &mut self,
+        ident: I,
+        text: &str,
+        style_id: i32,
+        speaker_id: i64,
+        options: SynthesizeOptions,
+    ) -> Result<(Vec<f32>, u32)> {
+        let output = self.easy_synthesize_detailed(ident, text, style_id, speaker_id, options)?;
+        Ok((
+            tts_util::array_to_samples(output.audio),
+            tts_util::SAMPLE_RATE,
+        ))
+    }
+
+    /// Synthesize text to audio and return a subtitle cue for each sentence
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let (audio, cues) = tts_holder.easy_synthesize_with_subtitles("tsukuyomi", "こんにちは", 0, 0, SynthesizeOptions::default())?;
+    /// std::fs::write("output.srt", sbv2_core::subtitle::to_srt(&cues))?;
+    /// ```
+    pub fn easy_synthesize_with_subtitles<I: Into<TTSIdent> + Copy>(
+        &mut self,
+        ident: I,
+        text: &str,
+        style_id: i32,
+        speaker_id: i64,
+        options: SynthesizeOptions,
+    ) -> Result<(Vec<u8>, Vec<SubtitleCue>)> {
+        let output = self.easy_synthesize_detailed(ident, text, style_id, speaker_id, options)?;
+        Ok((tts_util::array_to_vec(output.audio)?, output.subtitles))
+    }
+
+    /// Synthesize text to audio and return the timing information collected on the way
+    ///
+    /// # Examples
+    ///
+    /// ```rs
+    /// let output = tts_holder.easy_synthesize_detailed("tsukuyomi", "こんにちは", 0, 0, SynthesizeOptions::default())?;
+    /// let visemes = sbv2_core::viseme::viseme_timeline(&output.phonemes);
+    /// ```
+    pub fn easy_synthesize_detailed<I: Into<TTSIdent> + Copy>(
+        &mut self,
+        ident: I,
+        text: &str,
+        style_id: i32,
+        speaker_id: i64,
+        options: SynthesizeOptions,
+    ) -> Result<SynthesisOutput> {
+        let ident = ident.into();
+        self.find_and_load_model(ident.clone())?;
+        let speaker_count = self.speaker_count(ident.clone())?;
+        if speaker_id < 0 || speaker_count.is_some_and(|n| speaker_id as usize >= n) {
+            return Err(Error::ValueError(match speaker_count {
+                Some(n) => format!("Invalid speaker id {speaker_id}, the model has {n} speakers"),
+                None => format!("Invalid speaker id {speaker_id}"),
+            }));
+        }
+        let style_vector = self.get_style_vector(ident.clone(), style_id, options.style_weight)?;
+        let mut subtitles = vec![];
+        let mut phonemes = vec![];
+        let audio = if options.split_sentences {
+            let max_length = options.max_sentence_length.unwrap_or(usize::MAX);
+            let sentences = tts_util::split_sentences(text, max_length, &options.pauses, |s| {
+                tts_util::sentence_length(
+                    s,
+                    options.sentence_length_unit,
+                    &self.jtalk,
+                    &self.tokenizer,
+                )
+            });
+            let texts: Vec<&str> = sentences.iter().map(|s| s.text.as_str()).collect();
+            let parallel = options.parallel_sentences.min(self.parallel_sentences) > 1;
+            let outputs = if parallel && texts.len() > 1 {
+                self.synthesize_parallel(&ident, &texts, &style_vector, speaker_id, &options)?
+            } else {
+                self.parse_texts(&texts)?
+                    .into_iter()
+                    .map(|parsed| {
+                        self.synthesize_sentence(
+                            &ident,
+                            parsed,
+                            style_vector.clone(),
+                            speaker_id,
+                            &options,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            };
+            let sample_rate = tts_util::SAMPLE_RATE as f32;
+            let mut samples: Vec<f32> = vec![];
+            let mut previous = 0;
+            let mut gap = 0;
+            for (i, (sentence, (audio, mut timings))) in sentences.iter().zip(outputs).enumerate() {
+                let mut audio = tts_util::array_to_samples(audio);
+                let last = i == sentences.len() - 1;
+                let pause = if last {
+                    0
+                } else {
+                    (sentence.pause.max(0.0) * sample_rate) as usize
+                };
+                let start = match options.join {
+                    JoinStrategy::Crossfade(seconds) if i != 0 && gap == 0 => {
+                        let fade_out = if pause != 0 { seconds } else { 0.0 };
+                        postprocess::fade(&mut audio, 0.0, fade_out, tts_util::SAMPLE_RATE);
+                        let overlap = ((seconds.max(0.0) * sample_rate) as usize)
+                            .min(previous)
+                            .min(audio.len());
+                        let start = samples.len() - overlap;
+                        for (j, (x, y)) in samples[start..].iter_mut().zip(&audio).enumerate() {
+                            let t = j as f32 / overlap as f32;
+                            *x = *x * (1.0 - t) + y * t;
+                        }
+                        samples.extend_from_slice(&audio[overlap..]);
+                        start
+                    }
+                    // Crossfading with a pause is a fade into and out of the silence
+                    JoinStrategy::Crossfade(seconds) => {
+                        let fade_in = if i != 0 { seconds } else { 0.0 };
+                        let fade_out = if pause != 0 { seconds } else { 0.0 };
+                        postprocess::fade(&mut audio, fade_in, fade_out, tts_util::SAMPLE_RATE);
+                        let start = samples.len();
+                        samples.extend_from_slice(&audio);
+                        start
+                    }
+                    JoinStrategy::FadedSilence(seconds) => {
+                        let fade_in = if i != 0 { seconds } else { 0.0 };
+                        let fade_out = if !last { seconds } else { 0.0 };
+                        postprocess::fade(&mut audio, fade_in, fade_out, tts_util::SAMPLE_RATE);
+                        let start = samples.len();
+                        samples.extend_from_slice(&audio);
+                        start
+                    }
+                    _ => {
+                        let start = samples.len();
+                        samples.extend_from_slice(&audio);
+                        start
+                    }
+                };
+                subtitles.push(SubtitleCue::from_samples(
+                    start,
+                    start + audio.len(),
+                    tts_util::SAMPLE_RATE,
+                    &sentence.text,
+                ));
+                let shift = start as f32 / sample_rate;
+                for timing in timings.iter_mut() {
+                    timing.start += shift;
+                    timing.end += shift;
                 }
+                phonemes.extend(timings);
+                previous = audio.len();
+                gap = pause;
+                samples.resize(samples.len() + pause, 0.0);
+            }
+            if samples.is_empty() {
+                return Err(Error::ValueError("No text to synthesize".to_string()));
             }
-            concatenate(
-                Axis(2),
-                &audios.iter().map(|x| x.view()).collect::<Vec<_>>(),
-            )?
+            Array3::from_shape_vec((1, 1, samples.len()), samples)?
         } else {
-            let (bert_ori, phones, tones, lang_ids) = self.parse_text(text)?;
-
-            let vits2 = self
-                .find_model(ident)?
-                .vits2
-                .as_mut()
-                .ok_or(Error::ModelNotFoundError(ident.into().to_string()))?;
-            model::synthesize(
-                vits2,
-                bert_ori.to_owned(),
-                phones,
-                Array1::from_vec(vec![speaker_id]),
-                tones,
-                lang_ids,
-                style_vector,
-                options.sdp_ratio,
-                options.length_scale,
-                0.677,
-                0.8,
-            )?
+            let parsed = self.parse_text(text)?;
+            let (audio, timings) =
+                self.synthesize_sentence(&ident, parsed, style_vector, speaker_id, &options)?;
+            subtitles.push(SubtitleCue::from_samples(
+                0,
+                audio.shape()[2],
+                tts_util::SAMPLE_RATE,
+                text,
+            ));
+            phonemes = timings;
+            audio
+        };
+        let audio = if options.volume_scale != 1.0 {
+            audio * options.volume_scale
+        } else {
+            audio
         };
-        tts_util::array_to_vec(audio_array)
+        let audio = if options.post_process.is_enabled() {
+            let mut samples = tts_util::array_to_samples(audio);
+            let trimmed =
+                postprocess::process(&mut samples, &options.post_process, tts_util::SAMPLE_RATE);
+            let shift = trimmed as f32 / tts_util::SAMPLE_RATE as f32;
+            let duration = samples.len() as f32 / tts_util::SAMPLE_RATE as f32;
+            for cue in subtitles.iter_mut() {
+                cue.start = (cue.start - shift).clamp(0.0, duration);
+                cue.end = (cue.end - shift).clamp(0.0, duration);
+            }
+            for timing in phonemes.iter_mut() {
+                timing.start = (timing.start - shift).clamp(0.0, duration);
+                timing.end = (timing.end - shift).clamp(0.0, duration);
+            }
+            Array3::from_shape_vec((1, 1, samples.len()), samples)?
+        } else {
+            audio
+        };
+        Ok(SynthesisOutput {
+            audio,
+            subtitles,
+            phonemes,
+        })
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn synthesize_parsed(
+    vits2: &mut Session,
+    parsed: (Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>),
+    style_vector: Array1<f32>,
+    speaker_id: i64,
+    options: &SynthesizeOptions,
+) -> Result<(Array3<f32>, Vec<PhonemeTiming>)> {
+    let (bert_ori, phones, tones, lang_ids) = parsed;
+
+    let (mut audio, durations) = model::synthesize_with_durations(
+        vits2,
+        bert_ori.to_owned(),
+        phones.clone(),
+        Array1::from_vec(vec![speaker_id]),
+        tones,
+        lang_ids,
+        style_vector,
+        options.sdp_ratio,
+        options.length_scale,
+        0.677,
+        0.8,
+    )?;
+    if options.pitch_scale != 0.0 || options.intonation_scale != 1.0 {
+        let samples = pitch::modify(
+            &tts_util::array_to_samples(audio),
+            tts_util::SAMPLE_RATE,
+            options.pitch_scale,
+            options.intonation_scale,
+        );
+        audio = Array3::from_shape_vec((1, 1, samples.len()), samples)?;
     }
+    let phonemes = match durations {
+        Some(durations) => viseme::phoneme_timings(
+            &phones.to_vec(),
+            &durations.to_vec(),
+            tts_util::HOP_LENGTH as f32 / tts_util::SAMPLE_RATE as f32,
+            0.0,
+        ),
+        None => vec![],
+    };
+    Ok((audio, phonemes))
+}
+
+/// Audio and the timing information collected while synthesizing it
+///
+/// # Fields
+/// - `audio`: Audio array
+/// - `subtitles`: Subtitle cue for each sentence
+/// - `phonemes`: Timing of each phoneme, empty if the model does not expose durations
+pub struct SynthesisOutput {
+    pub audio: Array3<f32>,
+    pub subtitles: Vec<SubtitleCue>,
+    pub phonemes: Vec<PhonemeTiming>,
+}
+
+/// How sentences are joined together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinStrategy {
+    /// Insert the pause as digital silence with hard cuts
+    Silence,
+    /// Crossfade the sentence edges with the pause over this many seconds
+    ///
+    /// Sentences without a pause between them overlap by this many seconds instead.
+    Crossfade(f32),
+    /// Insert the pause and fade the sentence edges around it over this many seconds
+    FadedSilence(f32),
 }
 
 /// Synthesize options
@@ -356,11 +1096,30 @@ impl TTSModelHolder {
 /// - `length_scale`: Length scale
 /// - `style_weight`: Style weight
 /// - `split_sentences`: Split sentences
+/// - `max_sentence_length`: Split sentences longer than this at `、` or whitespace
+/// - `sentence_length_unit`: Unit of `max_sentence_length`
+/// - `pauses`: Pause inserted between sentences
+/// - `join`: How sentences are joined together
+/// - `parallel_sentences`: Maximum number of sentences synthesized concurrently, each with its own VITS
+///   session. Capped by `TTSModelHolder::set_parallel_sentences`, which defaults to 1
+/// - `volume_scale`: Gain applied to the audio
+/// - `pitch_scale`: Pitch shift in octaves, as VOICEVOX's `pitchScale`
+/// - `intonation_scale`: Scale of the pitch deviation from the mean, as VOICEVOX's `intonationScale`
+/// - `post_process`: Post-processing applied to the synthesized audio
 pub struct SynthesizeOptions {
     pub sdp_ratio: f32,
     pub length_scale: f32,
     pub style_weight: f32,
     pub split_sentences: bool,
+    pub max_sentence_length: Option<usize>,
+    pub sentence_length_unit: tts_util::LengthUnit,
+    pub pauses: tts_util::PauseOptions,
+    pub join: JoinStrategy,
+    pub parallel_sentences: usize,
+    pub volume_scale: f32,
+    pub pitch_scale: f32,
+    pub intonation_scale: f32,
+    pub post_process: PostProcessOptions,
 }
 
 impl Default for SynthesizeOptions {
@@ -370,6 +1129,15 @@ impl Default for SynthesizeOptions {
             length_scale: 1.0,
             style_weight: 1.0,
             split_sentences: true,
+            max_sentence_length: Some(80),
+            sentence_length_unit: tts_util::LengthUnit::Tokens,
+            pauses: tts_util::PauseOptions::default(),
+            join: JoinStrategy::Silence,
+            parallel_sentences: usize::MAX,
+            volume_scale: 1.0,
+            pitch_scale: 0.0,
+            intonation_scale: 1.0,
+            post_process: PostProcessOptions::default(),
         }
     }
 }
@@ -1,31 +1,99 @@
 use std::io::Cursor;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::metrics::{self, Stage};
 use crate::{jtalk, nlp, norm, tokenizer, utils};
 use hound::{SampleFormat, WavSpec, WavWriter};
 use ndarray::{concatenate, s, Array, Array1, Array2, Array3, Axis};
 use tokenizers::Tokenizer;
-/// Parse text and return the input for synthesize
+
+/// Sample rate of the audio produced by the VITS2 models
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Number of samples produced per predicted frame
+pub const HOP_LENGTH: usize = 512;
+
+/// Text converted to phonemes and BERT input, before BERT inference
+///
+/// # Fields
+/// - `phones`: Phoneme ids interspersed with blanks
+/// - `tones`: Tone of each phoneme
+/// - `lang_ids`: Language id of each phoneme
+/// - `word2ph`: Number of phonemes for each character, with the special tokens at both ends
+/// - `char_to_token`: Index of the BERT token each entry of `word2ph` belongs to
+/// - `token_ids`: BERT input ids
+/// - `attention_masks`: BERT attention mask
+pub struct PreparedText {
+    pub phones: Vec<i64>,
+    pub tones: Vec<i64>,
+    pub lang_ids: Vec<i64>,
+    pub word2ph: Vec<i32>,
+    pub char_to_token: Vec<usize>,
+    pub token_ids: Vec<i64>,
+    pub attention_masks: Vec<i64>,
+}
+
+impl PreparedText {
+    /// Expand the BERT features to phoneme level and return the input for synthesize
+    pub fn finish(
+        self,
+        bert_content: Array2<f32>,
+    ) -> Result<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)> {
+        let mut phone_level_feature = vec![];
+        for (reps, &i) in self.word2ph.iter().zip(&self.char_to_token) {
+            let repeat_feature = {
+                let (reps_rows, reps_cols) = (*reps, 1);
+                let arr_len = bert_content.slice(s![i, ..]).len();
+
+                let mut results: Array2<f32> =
+                    Array::zeros((reps_rows as usize, arr_len * reps_cols));
+
+                for j in 0..reps_rows {
+                    for k in 0..reps_cols {
+                        let mut view = results.slice_mut(s![j, k * arr_len..(k + 1) * arr_len]);
+                        view.assign(&bert_content.slice(s![i, ..]));
+                    }
+                }
+                results
+            };
+            phone_level_feature.push(repeat_feature);
+        }
+        let phone_level_feature = concatenate(
+            Axis(0),
+            &phone_level_feature
+                .iter()
+                .map(|x| x.view())
+                .collect::<Vec<_>>(),
+        )?;
+        let bert_ori = phone_level_feature.t();
+        Ok((
+            bert_ori.to_owned(),
+            self.phones.into(),
+            self.tones.into(),
+            self.lang_ids.into(),
+        ))
+    }
+}
+
+/// Convert text to phonemes and BERT input
 ///
 /// # Note
 /// This function is for low-level usage, use `easy_synthesize` for high-level usage.
-#[allow(clippy::type_complexity)]
-pub async fn parse_text(
+pub fn prepare_text(
     text: &str,
     jtalk: &jtalk::JTalk,
     tokenizer: &Tokenizer,
-    bert_predict: impl FnOnce(
-        Vec<i64>,
-        Vec<i64>,
-    ) -> std::pin::Pin<
-        Box<dyn std::future::Future<Output = Result<ndarray::Array2<f32>>>>,
-    >,
-) -> Result<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)> {
-    let text = jtalk.num2word(text)?;
-    let normalized_text = norm::normalize_text(&text);
+) -> Result<PreparedText> {
+    let normalized_text = metrics::time(Stage::Normalization, || -> Result<String> {
+        let text = jtalk.num2word(text)?;
+        Ok(norm::normalize_text(&text))
+    })?;
 
-    let process = jtalk.process_text(&normalized_text)?;
-    let (phones, tones, mut word2ph) = process.g2p()?;
+    let ((phones, tones, mut word2ph), seq_text) = metrics::time(Stage::G2p, || -> Result<_> {
+        let process = jtalk.process_text(&normalized_text)?;
+        let (seq_text, _) = process.text_to_seq_kata()?;
+        Ok((process.g2p()?, seq_text))
+    })?;
     let (phones, tones, lang_ids) = nlp::cleaned_text_to_sequence(phones, tones);
 
     let phones = utils::intersperse(&phones, 0);
@@ -36,13 +104,8 @@ pub async fn parse_text(
     }
     word2ph[0] += 1;
 
-    let text = {
-        let (seq_text, _) = process.text_to_seq_kata()?;
-        seq_text.join("")
-    };
-    let (token_ids, attention_masks) = tokenizer::tokenize(&text, tokenizer)?;
-
-    let bert_content = bert_predict(token_ids, attention_masks).await?;
+    let text = seq_text.join("");
+    let (token_ids, attention_masks, char_to_token) = tokenizer::tokenize(&text, tokenizer)?;
 
     assert!(
         word2ph.len() == text.chars().count() + 2,
@@ -51,38 +114,40 @@ pub async fn parse_text(
         normalized_text.chars().count()
     );
 
-    let mut phone_level_feature = vec![];
-    for (i, reps) in word2ph.iter().enumerate() {
-        let repeat_feature = {
-            let (reps_rows, reps_cols) = (*reps, 1);
-            let arr_len = bert_content.slice(s![i, ..]).len();
-
-            let mut results: Array2<f32> = Array::zeros((reps_rows as usize, arr_len * reps_cols));
+    Ok(PreparedText {
+        phones,
+        tones,
+        lang_ids,
+        word2ph,
+        char_to_token,
+        token_ids,
+        attention_masks,
+    })
+}
 
-            for j in 0..reps_rows {
-                for k in 0..reps_cols {
-                    let mut view = results.slice_mut(s![j, k * arr_len..(k + 1) * arr_len]);
-                    view.assign(&bert_content.slice(s![i, ..]));
-                }
-            }
-            results
-        };
-        phone_level_feature.push(repeat_feature);
-    }
-    let phone_level_feature = concatenate(
-        Axis(0),
-        &phone_level_feature
-            .iter()
-            .map(|x| x.view())
-            .collect::<Vec<_>>(),
-    )?;
-    let bert_ori = phone_level_feature.t();
-    Ok((
-        bert_ori.to_owned(),
-        phones.into(),
-        tones.into(),
-        lang_ids.into(),
-    ))
+/// Parse text and return the input for synthesize
+///
+/// # Note
+/// This function is for low-level usage, use `easy_synthesize` for high-level usage.
+#[allow(clippy::type_complexity)]
+pub async fn parse_text(
+    text: &str,
+    jtalk: &jtalk::JTalk,
+    tokenizer: &Tokenizer,
+    bert_predict: impl FnOnce(
+        Vec<i64>,
+        Vec<i64>,
+    ) -> std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<ndarray::Array2<f32>>>>,
+    >,
+) -> Result<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)> {
+    let mut prepared = prepare_text(text, jtalk, tokenizer)?;
+    let bert_content = bert_predict(
+        std::mem::take(&mut prepared.token_ids),
+        std::mem::take(&mut prepared.attention_masks),
+    )
+    .await?;
+    prepared.finish(bert_content)
 }
 
 /// Parse text and return the input for synthesize
@@ -96,85 +161,684 @@ pub fn parse_text_blocking(
     tokenizer: &Tokenizer,
     bert_predict: impl FnOnce(Vec<i64>, Vec<i64>) -> Result<ndarray::Array2<f32>>,
 ) -> Result<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)> {
-    let text = jtalk.num2word(text)?;
-    let normalized_text = norm::normalize_text(&text);
+    let mut prepared = prepare_text(text, jtalk, tokenizer)?;
+    let bert_content = bert_predict(
+        std::mem::take(&mut prepared.token_ids),
+        std::mem::take(&mut prepared.attention_masks),
+    )?;
+    prepared.finish(bert_content)
+}
 
-    let process = jtalk.process_text(&normalized_text)?;
-    let (phones, tones, mut word2ph) = process.g2p()?;
-    let (phones, tones, lang_ids) = nlp::cleaned_text_to_sequence(phones, tones);
+/// Parse several texts and return the input for synthesize for each of them
+///
+/// `bert_predict` receives the token ids and attention masks of all texts at once,
+/// so that they can be run in a single batch.
+///
+/// # Note
+/// This function is for low-level usage, use `easy_synthesize` for high-level usage.
+#[allow(clippy::type_complexity)]
+pub fn parse_texts_blocking(
+    texts: &[&str],
+    jtalk: &jtalk::JTalk,
+    tokenizer: &Tokenizer,
+    bert_predict: impl FnOnce(Vec<(Vec<i64>, Vec<i64>)>) -> Result<Vec<Array2<f32>>>,
+) -> Result<Vec<(Array2<f32>, Array1<i64>, Array1<i64>, Array1<i64>)>> {
+    let mut prepared = texts
+        .iter()
+        .map(|text| prepare_text(text, jtalk, tokenizer))
+        .collect::<Result<Vec<_>>>()?;
+    let inputs = prepared
+        .iter_mut()
+        .map(|p| {
+            (
+                std::mem::take(&mut p.token_ids),
+                std::mem::take(&mut p.attention_masks),
+            )
+        })
+        .collect();
+    let bert_contents = bert_predict(inputs)?;
+    if bert_contents.len() != prepared.len() {
+        return Err(Error::ValueError(format!(
+            "Expected {} BERT outputs, got {}",
+            prepared.len(),
+            bert_contents.len()
+        )));
+    }
+    prepared
+        .into_iter()
+        .zip(bert_contents)
+        .map(|(p, bert_content)| p.finish(bert_content))
+        .collect()
+}
 
-    let phones = utils::intersperse(&phones, 0);
-    let tones = utils::intersperse(&tones, 0);
-    let lang_ids = utils::intersperse(&lang_ids, 0);
-    for item in &mut word2ph {
-        *item *= 2;
+const SENTENCE_TERMINATORS: [char; 5] = ['。', '！', '？', '!', '?'];
+const CLAUSE_SEPARATORS: [char; 3] = ['、', '，', ','];
+const PAUSE_MARKERS: [char; 2] = ['…', '‥'];
+const OPENING_QUOTES: [char; 8] = ['「', '『', '（', '(', '“', '‘', '【', '《'];
+const CLOSING_QUOTES: [char; 8] = ['」', '』', '）', ')', '”', '’', '】', '》'];
+
+/// What ended a sentence returned by `split_sentences`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// `、` or whitespace
+    Clause,
+    /// `。！？` or a closing quote
+    Sentence,
+    /// Line break or the end of the text
+    Paragraph,
+    /// An explicit pause marker such as `…`
+    Marker,
+}
+
+/// Pause inserted after each kind of boundary
+///
+/// # Fields
+/// - `sentence`: Pause after `。！？` in seconds
+/// - `clause`: Pause after `、` in seconds
+/// - `paragraph`: Pause after a line break in seconds
+/// - `marker`: Pause after `…` and `‥` in seconds
+/// - `split_clauses`: Split at every `、` instead of only when a sentence is too long
+#[derive(Debug, Clone)]
+pub struct PauseOptions {
+    pub sentence: f32,
+    pub clause: f32,
+    pub paragraph: f32,
+    pub marker: f32,
+    pub split_clauses: bool,
+}
+
+impl Default for PauseOptions {
+    fn default() -> Self {
+        PauseOptions {
+            sentence: 0.3,
+            clause: 0.1,
+            paragraph: 0.5,
+            marker: 0.4,
+            split_clauses: false,
+        }
     }
-    word2ph[0] += 1;
+}
 
-    let text = {
-        let (seq_text, _) = process.text_to_seq_kata()?;
-        seq_text.join("")
-    };
-    let (token_ids, attention_masks) = tokenizer::tokenize(&text, tokenizer)?;
+impl PauseOptions {
+    /// Pause after the given boundary in seconds
+    pub fn pause(&self, boundary: Boundary) -> f32 {
+        match boundary {
+            Boundary::Clause => self.clause,
+            Boundary::Sentence => self.sentence,
+            Boundary::Paragraph => self.paragraph,
+            Boundary::Marker => self.marker,
+        }
+    }
+}
 
-    let bert_content = bert_predict(token_ids, attention_masks)?;
+/// A chunk of text small enough to synthesize in one pass
+///
+/// # Fields
+/// - `text`: Text of the chunk
+/// - `boundary`: What ended the chunk
+/// - `pause`: Pause to insert after the chunk in seconds
+#[derive(Debug, Clone)]
+pub struct Sentence {
+    pub text: String,
+    pub boundary: Boundary,
+    pub pause: f32,
+}
 
-    assert!(
-        word2ph.len() == text.chars().count() + 2,
-        "{} {}",
-        word2ph.len(),
-        normalized_text.chars().count()
-    );
+/// Unit used to measure sentences against the length budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthUnit {
+    Chars,
+    Tokens,
+    Morae,
+}
+
+impl std::str::FromStr for LengthUnit {
+    type Err = Error;
 
-    let mut phone_level_feature = vec![];
-    for (i, reps) in word2ph.iter().enumerate() {
-        let repeat_feature = {
-            let (reps_rows, reps_cols) = (*reps, 1);
-            let arr_len = bert_content.slice(s![i, ..]).len();
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "chars" => Ok(LengthUnit::Chars),
+            "tokens" => Ok(LengthUnit::Tokens),
+            "morae" => Ok(LengthUnit::Morae),
+            _ => Err(Error::ValueError(format!(
+                "Unknown sentence length unit {s}, expected chars, tokens or morae"
+            ))),
+        }
+    }
+}
+
+/// Measure the length of a text in the given unit
+///
+/// Falls back to the number of characters if the text cannot be tokenized or read.
+pub fn sentence_length(
+    text: &str,
+    unit: LengthUnit,
+    jtalk: &jtalk::JTalk,
+    tokenizer: &Tokenizer,
+) -> usize {
+    let length = match unit {
+        LengthUnit::Chars => None,
+        LengthUnit::Tokens => tokenizer.encode(text, false).ok().map(|e| e.len()),
+        LengthUnit::Morae => count_morae(text, jtalk).ok(),
+    };
+    length.unwrap_or_else(|| text.chars().count())
+}
+
+fn count_morae(text: &str, jtalk: &jtalk::JTalk) -> Result<usize> {
+    let text = jtalk.num2word(text)?;
+    let normalized_text = norm::normalize_text(&text);
+    let (_, seq_kata) = jtalk.process_text(&normalized_text)?.text_to_seq_kata()?;
+    Ok(seq_kata
+        .iter()
+        .flat_map(|k| k.chars())
+        .filter(|c| ('\u{30A1}'..='\u{30FC}').contains(c) && !"ァィゥェォャュョヮ".contains(*c))
+        .count())
+}
 
-            let mut results: Array2<f32> = Array::zeros((reps_rows as usize, arr_len * reps_cols));
+/// Split text into sentences on `。！？`, closing quotes and pause markers
+///
+/// Sentences never break inside quotes. Sentences longer than `max_length`, as measured by
+/// `measure`, are split again at `、` or whitespace, and as a last resort between characters,
+/// avoiding the inside of numbers and kana runs.
+/// The pause after each sentence is taken from `pauses`.
+///
+/// # Examples
+///
+/// ```rs
+/// let sentences = split_sentences(text, 80, &PauseOptions::default(), |s| s.chars().count());
+/// ```
+pub fn split_sentences(
+    text: &str,
+    max_length: usize,
+    pauses: &PauseOptions,
+    measure: impl Fn(&str) -> usize,
+) -> Vec<Sentence> {
+    let mut chunks: Vec<(String, Boundary)> = vec![];
+    for paragraph in text.lines() {
+        for (sentence, boundary) in split_paragraph(paragraph) {
+            if sentence.trim().is_empty() {
+                continue;
+            }
+            let clauses = if pauses.split_clauses {
+                split_clauses(&sentence, false)
+            } else {
+                vec![sentence]
+            };
+            for clause in clauses {
+                let pieces = if measure(&clause) > max_length {
+                    split_long(&clause, max_length, &measure)
+                } else {
+                    vec![clause]
+                };
+                chunks.extend(
+                    pieces
+                        .into_iter()
+                        .filter(|text| !text.trim().is_empty())
+                        .map(|text| (text, Boundary::Clause)),
+                );
+            }
+            if let Some(last) = chunks.last_mut() {
+                last.1 = boundary;
+            }
+        }
+        if let Some(last) = chunks.last_mut() {
+            last.1 = Boundary::Paragraph;
+        }
+    }
+    chunks
+        .into_iter()
+        .map(|(text, boundary)| Sentence {
+            text,
+            boundary,
+            pause: pauses.pause(boundary),
+        })
+        .collect()
+}
 
-            for j in 0..reps_rows {
-                for k in 0..reps_cols {
-                    let mut view = results.slice_mut(s![j, k * arr_len..(k + 1) * arr_len]);
-                    view.assign(&bert_content.slice(s![i, ..]));
+fn split_paragraph(paragraph: &str) -> Vec<(String, Boundary)> {
+    let chars: Vec<char> = paragraph.chars().collect();
+    let mut sentences = vec![];
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        current.push(c);
+        i += 1;
+        let boundary = if OPENING_QUOTES.contains(&c) {
+            depth += 1;
+            None
+        } else if CLOSING_QUOTES.contains(&c) {
+            depth = depth.saturating_sub(1);
+            // 「はい」「いいえ」
+            (depth == 0 && chars.get(i).is_some_and(|n| OPENING_QUOTES.contains(n)))
+                .then_some(Boundary::Sentence)
+        } else if depth == 0 && (SENTENCE_TERMINATORS.contains(&c) || PAUSE_MARKERS.contains(&c)) {
+            let mut boundary = if PAUSE_MARKERS.contains(&c) {
+                Boundary::Marker
+            } else {
+                Boundary::Sentence
+            };
+            while let Some(&n) = chars.get(i) {
+                if SENTENCE_TERMINATORS.contains(&n) {
+                    boundary = Boundary::Sentence;
+                } else if !PAUSE_MARKERS.contains(&n) && !CLOSING_QUOTES.contains(&n) {
+                    break;
                 }
+                current.push(n);
+                i += 1;
             }
-            results
+            Some(boundary)
+        } else {
+            None
         };
-        phone_level_feature.push(repeat_feature);
+        if let Some(boundary) = boundary {
+            sentences.push((std::mem::take(&mut current), boundary));
+        }
     }
-    let phone_level_feature = concatenate(
-        Axis(0),
-        &phone_level_feature
-            .iter()
-            .map(|x| x.view())
-            .collect::<Vec<_>>(),
-    )?;
-    let bert_ori = phone_level_feature.t();
-    Ok((
-        bert_ori.to_owned(),
-        phones.into(),
-        tones.into(),
-        lang_ids.into(),
-    ))
+    if !current.is_empty() {
+        sentences.push((current, Boundary::Sentence));
+    }
+    sentences
 }
 
-pub fn array_to_vec(audio_array: Array3<f32>) -> Result<Vec<u8>> {
-    let spec = WavSpec {
-        channels: 1,
-        sample_rate: 44100,
-        bits_per_sample: 32,
-        sample_format: SampleFormat::Float,
-    };
-    let mut cursor = Cursor::new(Vec::new());
-    let mut writer = WavWriter::new(&mut cursor, spec)?;
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || ('０'..='９').contains(&c)
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3041}'..='\u{30ff}').contains(&c) || ('\u{ff66}'..='\u{ff9f}').contains(&c)
+}
+
+/// Whether a chunk may end between `prev` and `next`, numbers and kana runs are kept together
+fn can_break(prev: char, next: char) -> bool {
+    !((is_digit(prev) && is_digit(next)) || (is_kana(prev) && is_kana(next)))
+}
+
+fn split_clauses(sentence: &str, whitespace: bool) -> Vec<String> {
+    let chars: Vec<char> = sentence.chars().collect();
+    let mut clauses = vec![];
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        current.push(c);
+        // 1,000
+        let in_number =
+            i > 0 && is_digit(chars[i - 1]) && chars.get(i + 1).is_some_and(|&n| is_digit(n));
+        if (CLAUSE_SEPARATORS.contains(&c) && !in_number) || (whitespace && c.is_whitespace()) {
+            clauses.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        clauses.push(current);
+    }
+    clauses
+}
+
+fn split_long(sentence: &str, max_length: usize, measure: &impl Fn(&str) -> usize) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut current = String::new();
+    for clause in split_clauses(sentence, true) {
+        if !current.is_empty() && measure(&format!("{current}{clause}")) > max_length {
+            pieces.push(std::mem::take(&mut current));
+        }
+        current.push_str(&clause);
+    }
+    if !current.is_empty() {
+        pieces.push(current);
+    }
+
+    let mut result = vec![];
+    for piece in pieces {
+        if measure(&piece) <= max_length {
+            result.push(piece);
+            continue;
+        }
+        let chars: Vec<char> = piece.chars().collect();
+        let mut start = 0;
+        let mut last_break = None;
+        for i in 1..chars.len() {
+            if can_break(chars[i - 1], chars[i]) {
+                last_break = Some(i);
+            }
+            if measure(&chars[start..=i].iter().collect::<String>()) > max_length {
+                // Runs longer than the budget are cut anyway
+                let end = last_break.filter(|&b| b > start).unwrap_or(i);
+                result.push(chars[start..end].iter().collect());
+                start = end;
+            }
+        }
+        if start < chars.len() {
+            result.push(chars[start..].iter().collect());
+        }
+    }
+    result
+}
+
+/// Whether `text` ends with `。！？` or a pause marker outside of any quote
+///
+/// Closing quotes after the terminator are allowed, as in `「はい。」`.
+fn ends_sentence(text: &str) -> bool {
+    let mut depth = 0usize;
+    for c in text.chars() {
+        if OPENING_QUOTES.contains(&c) {
+            depth += 1;
+        } else if CLOSING_QUOTES.contains(&c) {
+            depth = depth.saturating_sub(1);
+        }
+    }
+    let last = text
+        .trim_end()
+        .trim_end_matches(&CLOSING_QUOTES[..])
+        .chars()
+        .last();
+    depth == 0
+        && last.is_some_and(|c| SENTENCE_TERMINATORS.contains(&c) || PAUSE_MARKERS.contains(&c))
+}
+
+/// Incremental sentence splitter for streamed text
+///
+/// Uses the same rules as `split_sentences`, measuring length in characters. The last
+/// sentence is held back until more text follows it, so quotes, numbers and terminator
+/// runs like `！？」` are never cut by a delta boundary. It is only released early when
+/// it ends with a terminator outside of quotes and whitespace follows, or at a line break.
+///
+/// # Examples
+///
+/// ```rs
+/// let mut segmenter = SentenceSegmenter::new(Some(80), PauseOptions::default());
+/// let sentences = segmenter.push("こんにちは。今日は"); // こんにちは。
+/// let sentences = segmenter.flush(); // 今日は
+/// ```
+#[derive(Debug, Clone)]
+pub struct SentenceSegmenter {
+    buffer: String,
+    max_length: Option<usize>,
+    pauses: PauseOptions,
+}
+
+impl SentenceSegmenter {
+    pub fn new(max_length: Option<usize>, pauses: PauseOptions) -> Self {
+        SentenceSegmenter {
+            buffer: String::new(),
+            max_length,
+            pauses,
+        }
+    }
+
+    /// Append a text delta and return the sentences it completed
+    pub fn push(&mut self, delta: &str) -> Vec<Sentence> {
+        self.buffer.push_str(delta);
+        let mut sentences = self.split();
+        let complete = self.buffer.ends_with('\n')
+            || (self.buffer.ends_with(char::is_whitespace)
+                && sentences.last().is_some_and(|s| ends_sentence(&s.text)));
+        if complete {
+            self.buffer.clear();
+        } else {
+            self.buffer = sentences.pop().map(|s| s.text).unwrap_or_default();
+        }
+        sentences
+    }
+
+    /// Return everything left in the buffer
+    pub fn flush(&mut self) -> Vec<Sentence> {
+        let sentences = self.split();
+        self.buffer.clear();
+        sentences
+    }
+
+    /// Text that has not been returned yet
+    pub fn pending(&self) -> &str {
+        &self.buffer
+    }
+
+    fn split(&self) -> Vec<Sentence> {
+        split_sentences(
+            &self.buffer,
+            self.max_length.unwrap_or(usize::MAX),
+            &self.pauses,
+            |s| s.chars().count(),
+        )
+    }
+}
+
+/// Flatten an audio array into raw samples at `SAMPLE_RATE`
+pub fn array_to_samples(audio_array: Array3<f32>) -> Vec<f32> {
+    let mut samples = Vec::with_capacity(audio_array.len());
     for i in 0..audio_array.shape()[0] {
-        let output = audio_array.slice(s![i, 0, ..]).to_vec();
-        for sample in output {
-            writer.write_sample(sample)?;
+        samples.extend(audio_array.slice(s![i, 0, ..]).iter());
+    }
+    samples
+}
+
+pub fn array_to_vec(audio_array: Array3<f32>) -> Result<Vec<u8>> {
+    samples_to_wav(&array_to_samples(audio_array), SAMPLE_RATE, 1)
+}
+
+/// Encode mono samples as a 32-bit float wav, copying them to every channel
+pub fn samples_to_wav(samples: &[f32], sample_rate: u32, channels: u16) -> Result<Vec<u8>> {
+    metrics::time(Stage::Encoding, || {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut cursor, spec)?;
+        for &sample in samples {
+            for _ in 0..channels {
+                writer.write_sample(sample)?;
+            }
         }
+        writer.finalize()?;
+        Ok(cursor.into_inner())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(sentences: Vec<Sentence>) -> Vec<String> {
+        sentences.into_iter().map(|s| s.text).collect()
+    }
+
+    fn new_segmenter() -> SentenceSegmenter {
+        SentenceSegmenter::new(None, PauseOptions::default())
+    }
+
+    fn chars(text: &str) -> usize {
+        text.chars().count()
+    }
+
+    fn split(text: &str, max_length: usize) -> Vec<String> {
+        texts(split_sentences(
+            text,
+            max_length,
+            &PauseOptions::default(),
+            chars,
+        ))
+    }
+
+    #[test]
+    fn split_sentences_keeps_quotes_together() {
+        assert_eq!(
+            split("彼は「はい。いいえ。」と言った。次です。", usize::MAX),
+            vec!["彼は「はい。いいえ。」と言った。", "次です。"]
+        );
+        assert_eq!(
+            split("「はい」「いいえ」", usize::MAX),
+            vec!["「はい」", "「いいえ」"]
+        );
+        assert_eq!(
+            split("本当！？」そうです。", usize::MAX),
+            vec!["本当！？」", "そうです。"]
+        );
+    }
+
+    #[test]
+    fn split_sentences_assigns_boundaries() {
+        let pauses = PauseOptions::default();
+        let sentences =
+            split_sentences("こんにちは。\n元気…ですか\n\n", usize::MAX, &pauses, chars);
+        let boundaries: Vec<_> = sentences
+            .iter()
+            .map(|s| (s.text.as_str(), s.boundary))
+            .collect();
+        assert_eq!(
+            boundaries,
+            vec![
+                ("こんにちは。", Boundary::Paragraph),
+                ("元気…", Boundary::Marker),
+                ("ですか", Boundary::Paragraph),
+            ]
+        );
+        assert_eq!(sentences[1].pause, pauses.marker);
+    }
+
+    #[test]
+    fn split_sentences_ignores_whitespace_chunks() {
+        let pauses = PauseOptions::default();
+        let boundaries = |text| {
+            split_sentences(text, usize::MAX, &pauses, chars)
+                .into_iter()
+                .map(|s| (s.text, s.boundary))
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            ("文。".to_string(), Boundary::Paragraph),
+            ("次。".to_string(), Boundary::Paragraph),
+        ];
+        assert_eq!(boundaries("文。 \n次。"), expected);
+        assert_eq!(boundaries("文。\r\n次。\r\n"), expected);
+        assert_eq!(
+            boundaries("文。\r\n\r\n次\r\n"),
+            vec![
+                ("文。".to_string(), Boundary::Paragraph),
+                ("次".to_string(), Boundary::Paragraph),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_sentences_keeps_numbers_together() {
+        assert_eq!(
+            split("価格は1,000円、送料は500円です。", 10),
+            vec!["価格は1,000円、", "送料は500円です。"]
+        );
+        let pauses = PauseOptions {
+            split_clauses: true,
+            ..Default::default()
+        };
+        let sentences = split_sentences("1,000円、2,000円。", usize::MAX, &pauses, chars);
+        let boundaries: Vec<_> = sentences
+            .iter()
+            .map(|s| (s.text.as_str(), s.boundary))
+            .collect();
+        assert_eq!(
+            boundaries,
+            vec![
+                ("1,000円、", Boundary::Clause),
+                ("2,000円。", Boundary::Paragraph)
+            ]
+        );
+    }
+
+    #[test]
+    fn split_long_prefers_clauses_and_whitespace() {
+        assert_eq!(
+            split_long("今日は晴れ、明日は雨", 6, &chars),
+            vec!["今日は晴れ、", "明日は雨"]
+        );
+        assert_eq!(
+            split_long("Hello world again", 11, &chars),
+            vec!["Hello ", "world again"]
+        );
+    }
+
+    #[test]
+    fn split_long_keeps_kana_runs() {
+        assert_eq!(
+            split_long("今日はありがとう", 6, &chars),
+            vec!["今日", "はありがとう"]
+        );
+    }
+
+    #[test]
+    fn split_long_cuts_runs_over_the_budget() {
+        assert_eq!(
+            split_long("1234567890", 4, &chars),
+            vec!["1234", "5678", "90"]
+        );
+        assert_eq!(
+            split_long("ありがとうございました", 4, &chars),
+            vec!["ありがと", "うござい", "ました"]
+        );
+        assert!(split_long("今日はありがとう", 6, &chars)
+            .iter()
+            .all(|piece| chars(piece) <= 6));
+    }
+
+    #[test]
+    fn segmenter_holds_open_quotes() {
+        let mut segmenter = new_segmenter();
+        assert!(segmenter.push("「今日は ").is_empty());
+        assert_eq!(segmenter.pending(), "「今日は ");
+        assert!(segmenter.push("晴れ。").is_empty());
+        assert!(segmenter.push("」").is_empty());
+        assert_eq!(texts(segmenter.push(" ")), vec!["「今日は 晴れ。」 "]);
+        assert_eq!(segmenter.pending(), "");
+    }
+
+    #[test]
+    fn segmenter_holds_numbers() {
+        let mut segmenter = new_segmenter();
+        assert!(segmenter.push("価格は1, ").is_empty());
+        assert!(segmenter.push("000円です").is_empty());
+        assert_eq!(texts(segmenter.push("。 ")), vec!["価格は1, 000円です。"]);
+
+        let mut segmenter = new_segmenter();
+        assert!(segmenter.push("価格は1,").is_empty());
+        assert!(segmenter.push("000円です。").is_empty());
+        assert_eq!(texts(segmenter.flush()), vec!["価格は1,000円です。"]);
+    }
+
+    #[test]
+    fn segmenter_holds_terminator_runs() {
+        let mut segmenter = new_segmenter();
+        assert!(segmenter.push("「本当！").is_empty());
+        assert!(segmenter.push("？").is_empty());
+        assert!(segmenter.push("」").is_empty());
+        assert_eq!(texts(segmenter.push("「はい")), vec!["「本当！？」"]);
+        assert!(segmenter.push("。」そうです！").is_empty());
+        assert!(segmenter.push("？").is_empty());
+        assert_eq!(texts(segmenter.push("次")), vec!["「はい。」そうです！？"]);
+        assert_eq!(texts(segmenter.flush()), vec!["次"]);
+    }
+
+    #[test]
+    fn segmenter_releases_finished_sentences() {
+        let mut segmenter = new_segmenter();
+        assert_eq!(
+            texts(segmenter.push("こんにちは。今日は")),
+            vec!["こんにちは。"]
+        );
+        assert_eq!(segmenter.pending(), "今日は");
+        assert_eq!(
+            texts(segmenter.push("晴れです！ ")),
+            vec!["今日は晴れです！"]
+        );
+        assert_eq!(segmenter.pending(), "");
+        assert_eq!(texts(segmenter.push("見出し\n")), vec!["見出し"]);
+    }
+
+    #[test]
+    fn segmenter_holds_fragments_before_spaces() {
+        let mut segmenter = new_segmenter();
+        assert!(segmenter.push("Hello ").is_empty());
+        assert!(segmenter.push("world ").is_empty());
+        assert_eq!(texts(segmenter.push("again! ")), vec!["Hello world again!"]);
+        assert!(segmenter.flush().is_empty());
     }
-    writer.finalize()?;
-    Ok(cursor.into_inner())
 }
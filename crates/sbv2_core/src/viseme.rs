@@ -0,0 +1,232 @@
+use crate::norm::{PUNCTUATIONS, SYMBOLS};
+use serde::Serialize;
+
+/// Japanese mouth shape
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Viseme {
+    A,
+    I,
+    U,
+    E,
+    O,
+    #[serde(rename = "N")]
+    N,
+    Closed,
+}
+
+impl std::fmt::Display for Viseme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Viseme::A => "a",
+            Viseme::I => "i",
+            Viseme::U => "u",
+            Viseme::E => "e",
+            Viseme::O => "o",
+            Viseme::N => "N",
+            Viseme::Closed => "closed",
+        })
+    }
+}
+
+impl Viseme {
+    /// Mouth shape of a vowel, devoiced vowels (`I`, `U`, ...) included
+    fn from_vowel(phoneme: &str) -> Option<Self> {
+        match phoneme {
+            "a" | "a:" | "A" => Some(Viseme::A),
+            "i" | "i:" | "I" => Some(Viseme::I),
+            "u" | "u:" | "U" => Some(Viseme::U),
+            "e" | "e:" | "E" => Some(Viseme::E),
+            "o" | "o:" | "O" => Some(Viseme::O),
+            "N" => Some(Viseme::N),
+            _ => None,
+        }
+    }
+
+    fn is_closed(phoneme: &str) -> bool {
+        matches!(
+            phoneme,
+            "m" | "my" | "b" | "by" | "p" | "py" | "q" | "_" | "SP" | "UNK"
+        ) || PUNCTUATIONS.contains(&phoneme)
+    }
+}
+
+/// Start and end time of a phoneme in seconds
+#[derive(Debug, Clone, Serialize)]
+pub struct PhonemeTiming {
+    pub phoneme: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Start and end time of a mouth shape in seconds
+#[derive(Debug, Clone, Serialize)]
+pub struct VisemeCue {
+    pub viseme: Viseme,
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Map predicted durations back to the phoneme sequence
+///
+/// `phones` is the interspersed phoneme id sequence fed to the model and `durations` the
+/// predicted number of frames for each of them. The duration of a blank is merged into
+/// the phoneme before it.
+pub fn phoneme_timings(
+    phones: &[i64],
+    durations: &[f32],
+    frame_seconds: f32,
+    offset: f32,
+) -> Vec<PhonemeTiming> {
+    let mut timings: Vec<PhonemeTiming> = vec![];
+    let mut end = offset;
+    for (i, (&phone, &duration)) in phones.iter().zip(durations.iter()).enumerate() {
+        end += duration * frame_seconds;
+        if i % 2 == 1 {
+            timings.push(PhonemeTiming {
+                phoneme: SYMBOLS.get(phone as usize).unwrap_or(&"UNK").to_string(),
+                start: timings.last().map_or(offset, |t| t.end),
+                end,
+            });
+        } else if let Some(last) = timings.last_mut() {
+            last.end = end;
+        }
+    }
+    timings
+}
+
+/// Build a viseme timeline (a/i/u/e/o/N/closed) from phoneme timings
+///
+/// Consonants take the mouth shape of the following vowel, except bilabials which close the mouth.
+pub fn viseme_timeline(phonemes: &[PhonemeTiming]) -> Vec<VisemeCue> {
+    let mut cues: Vec<VisemeCue> = vec![];
+    for (i, timing) in phonemes.iter().enumerate() {
+        let viseme = if let Some(viseme) = Viseme::from_vowel(&timing.phoneme) {
+            viseme
+        } else if Viseme::is_closed(&timing.phoneme) {
+            Viseme::Closed
+        } else {
+            phonemes[i + 1..]
+                .iter()
+                .take_while(|t| !Viseme::is_closed(&t.phoneme))
+                .find_map(|t| Viseme::from_vowel(&t.phoneme))
+                .unwrap_or(Viseme::Closed)
+        };
+        match cues.last_mut() {
+            Some(last) if last.viseme == viseme => last.end = timing.end,
+            _ => cues.push(VisemeCue {
+                viseme,
+                start: timing.start,
+                end: timing.end,
+            }),
+        }
+    }
+    cues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Timings of 0.1 seconds each
+    fn timings(phonemes: &[&str]) -> Vec<PhonemeTiming> {
+        phonemes
+            .iter()
+            .enumerate()
+            .map(|(i, phoneme)| PhonemeTiming {
+                phoneme: phoneme.to_string(),
+                start: i as f32 * 0.1,
+                end: (i + 1) as f32 * 0.1,
+            })
+            .collect()
+    }
+
+    fn symbol(phoneme: &str) -> i64 {
+        SYMBOLS.iter().position(|s| *s == phoneme).unwrap() as i64
+    }
+
+    #[test]
+    fn viseme_of_each_phoneme() {
+        let table = [
+            ("a", Viseme::A),
+            ("i:", Viseme::I),
+            ("u", Viseme::U),
+            ("e:", Viseme::E),
+            ("o", Viseme::O),
+            ("N", Viseme::N),
+            // devoiced vowels as in です
+            ("I", Viseme::I),
+            ("U", Viseme::U),
+            // pau and sil
+            ("_", Viseme::Closed),
+            ("SP", Viseme::Closed),
+            (",", Viseme::Closed),
+            ("?", Viseme::Closed),
+            ("m", Viseme::Closed),
+            ("by", Viseme::Closed),
+            ("p", Viseme::Closed),
+            ("q", Viseme::Closed),
+            // other consonants take the shape of the following vowel
+            ("k", Viseme::E),
+            ("sh", Viseme::E),
+            ("w", Viseme::E),
+        ];
+        for (phoneme, viseme) in table {
+            let cues = viseme_timeline(&timings(&[phoneme, "e"]));
+            assert_eq!(cues[0].viseme, viseme, "{phoneme}");
+        }
+        // but not across a pause
+        let cues = viseme_timeline(&timings(&["k", "_", "a"]));
+        assert_eq!(cues[0].viseme, Viseme::Closed);
+        assert_eq!(cues[0].end, 0.2);
+    }
+
+    #[test]
+    fn viseme_timeline_merges_cues() {
+        // _ k o N n i ch i w a _
+        let cues = viseme_timeline(&timings(&[
+            "_", "k", "o", "N", "n", "i", "ch", "i", "w", "a", "_",
+        ]));
+        let cues: Vec<_> = cues
+            .iter()
+            .map(|c| (c.viseme, (c.start * 10.0).round(), (c.end * 10.0).round()))
+            .collect();
+        assert_eq!(
+            cues,
+            vec![
+                (Viseme::Closed, 0.0, 1.0),
+                (Viseme::O, 1.0, 3.0),
+                (Viseme::N, 3.0, 4.0),
+                (Viseme::I, 4.0, 8.0),
+                (Viseme::A, 8.0, 10.0),
+                (Viseme::Closed, 10.0, 11.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn phoneme_timings_from_durations() {
+        // blanks are interspersed, the leading one counts towards the first phoneme
+        let phones = [0, symbol("_"), 0, symbol("k"), 0, symbol("a"), 0];
+        let durations = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let timings = phoneme_timings(&phones, &durations, 0.01, 1.0);
+        let timings: Vec<_> = timings
+            .iter()
+            .map(|t| {
+                (
+                    t.phoneme.as_str(),
+                    (t.start * 100.0).round(),
+                    (t.end * 100.0).round(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            timings,
+            vec![
+                ("_", 100.0, 106.0),
+                ("k", 106.0, 115.0),
+                ("a", 115.0, 128.0)
+            ]
+        );
+    }
+}
@@ -11,4 +11,9 @@ await holder.load(
 	await fs.readFile("../../models/tsukuyomi.sbv2"),
 );
 await fs.writeFile("out.wav", await holder.synthesize("tsukuyomi", "おはよう"));
+const { samples, sampleRate } = await holder.synthesizeSamples(
+	"tsukuyomi",
+	"こんばんは",
+);
+console.log(`${samples.length / sampleRate}s`);
 holder.unload("tsukuyomi");
@@ -1,6 +1,12 @@
 import * as wasm from "../pkg/sbv2_wasm.js";
 import { InferenceSession, Tensor } from "onnxruntime-web";
 
+/**
+ * Splits streamed text into sentences: `push(delta)` and `flush()` return
+ * complete sentences, and `pending` holds the text not returned yet.
+ */
+export { SentenceSegmenterWrap as SentenceSegmenter } from "../pkg/sbv2_wasm.js";
+
 export class ModelHolder {
 	private models: Map<string, [InferenceSession, wasm.StyleVectorWrap]> =
 		new Map();
@@ -27,11 +33,71 @@ export class ModelHolder {
 		style_weight: number = 1.0,
 		sdp_ratio: number = 0.4,
 		speed: number = 1.0,
+		max_sentence_length: number | null = 80,
+		sentence_length_unit: "chars" | "tokens" | "morae" = "tokens",
+	) {
+		return this.run(
+			wasm.synthesize,
+			name,
+			text,
+			style_id,
+			style_weight,
+			sdp_ratio,
+			speed,
+			max_sentence_length,
+			sentence_length_unit,
+		);
+	}
+	public async synthesizeSamples(
+		name: string,
+		text: string,
+		style_id: number = 0,
+		style_weight: number = 1.0,
+		sdp_ratio: number = 0.4,
+		speed: number = 1.0,
+		max_sentence_length: number | null = 80,
+		sentence_length_unit: "chars" | "tokens" | "morae" = "tokens",
+	) {
+		const samples = await this.run(
+			wasm.synthesize_samples,
+			name,
+			text,
+			style_id,
+			style_weight,
+			sdp_ratio,
+			speed,
+			max_sentence_length,
+			sentence_length_unit,
+		);
+		return { samples, sampleRate: wasm.sample_rate() };
+	}
+	private async run<T>(
+		synthesize: (
+			text: string,
+			tokenizer: wasm.TokenizerWrap,
+			bert_predict_fn: Function,
+			synthesize_fn: Function,
+			sdp_ratio: number,
+			length_scale: number,
+			style_id: number,
+			style_weight: number,
+			style_vectors: wasm.StyleVectorWrap,
+			max_sentence_length: number | null,
+			sentence_length_unit: string,
+		) => Promise<T>,
+		name: string,
+		text: string,
+		style_id: number,
+		style_weight: number,
+		sdp_ratio: number,
+		speed: number,
+		max_sentence_length: number | null,
+		sentence_length_unit: string,
 	) {
 		const mod = this.models.get(name);
 		if (!mod) throw new Error(`No model named ${name}`);
 		const [vits2, style] = mod;
-		return wasm.synthesize(
+		return synthesize(
 			text,
 			this.tok,
 			async (a: BigInt64Array, b: BigInt64Array) => {
@@ -89,6 +155,8 @@ export class ModelHolder {
 			style_id,
 			style_weight,
 			style,
+			max_sentence_length,
+			sentence_length_unit,
 		);
 	}
 	public async load(name: string, b: Uint8Array) {
@@ -41,6 +41,47 @@ pub fn load_sbv2file(buf: js_sys::Uint8Array) -> Result<js_sys::Array, JsError>
     ]))
 }
 
+#[wasm_bindgen]
+pub fn sample_rate() -> u32 {
+    tts_util::SAMPLE_RATE
+}
+
+#[wasm_bindgen]
+pub struct SentenceSegmenterWrap {
+    segmenter: tts_util::SentenceSegmenter,
+}
+
+fn sentences_to_array(sentences: Vec<tts_util::Sentence>) -> js_sys::Array {
+    array_helper::vec_to_array(sentences.into_iter().map(|s| s.text.into()).collect())
+}
+
+#[wasm_bindgen]
+impl SentenceSegmenterWrap {
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_length: Option<usize>) -> Self {
+        SentenceSegmenterWrap {
+            segmenter: tts_util::SentenceSegmenter::new(
+                max_length,
+                tts_util::PauseOptions::default(),
+            ),
+        }
+    }
+
+    pub fn push(&mut self, delta: &str) -> js_sys::Array {
+        sentences_to_array(self.segmenter.push(delta))
+    }
+
+    pub fn flush(&mut self) -> js_sys::Array {
+        sentences_to_array(self.segmenter.flush())
+    }
+
+    /// Text that has not been returned yet
+    #[wasm_bindgen(getter)]
+    pub fn pending(&self) -> String {
+        self.segmenter.pending().to_string()
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 #[wasm_bindgen]
 pub async fn synthesize(
@@ -53,7 +94,75 @@ pub async fn synthesize(
     style_id: i32,
     style_weight: f32,
     style_vectors: &StyleVectorWrap,
+    max_sentence_length: Option<usize>,
+    sentence_length_unit: &str,
 ) -> Result<js_sys::Uint8Array, JsError> {
+    let audio = synthesize_array(
+        text,
+        tokenizer,
+        bert_predict_fn,
+        synthesize_fn,
+        sdp_ratio,
+        length_scale,
+        style_id,
+        style_weight,
+        style_vectors,
+        max_sentence_length,
+        sentence_length_unit,
+    )
+    .await?;
+    Ok(array_helper::vec8_to_array8(tts_util::array_to_vec(audio)?))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub async fn synthesize_samples(
+    text: &str,
+    tokenizer: &TokenizerWrap,
+    bert_predict_fn: js_sys::Function,
+    synthesize_fn: js_sys::Function,
+    sdp_ratio: f32,
+    length_scale: f32,
+    style_id: i32,
+    style_weight: f32,
+    style_vectors: &StyleVectorWrap,
+    max_sentence_length: Option<usize>,
+    sentence_length_unit: &str,
+) -> Result<js_sys::Float32Array, JsError> {
+    let audio = synthesize_array(
+        text,
+        tokenizer,
+        bert_predict_fn,
+        synthesize_fn,
+        sdp_ratio,
+        length_scale,
+        style_id,
+        style_weight,
+        style_vectors,
+        max_sentence_length,
+        sentence_length_unit,
+    )
+    .await?;
+    Ok(array_helper::vec_f32_to_array_f32(
+        tts_util::array_to_samples(audio),
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn synthesize_array(
+    text: &str,
+    tokenizer: &TokenizerWrap,
+    bert_predict_fn: js_sys::Function,
+    synthesize_fn: js_sys::Function,
+    sdp_ratio: f32,
+    length_scale: f32,
+    style_id: i32,
+    style_weight: f32,
+    style_vectors: &StyleVectorWrap,
+    max_sentence_length: Option<usize>,
+    sentence_length_unit: &str,
+) -> Result<ndarray::Array3<f32>, JsError> {
+    let synthesize_fn = &synthesize_fn;
     let synthesize_wrap = |bert_ori: ndarray::Array2<f32>,
                            x_tst: ndarray::Array1<i64>,
                            tones: ndarray::Array1<i64>,
@@ -82,42 +191,66 @@ pub async fn synthesize(
             })?;
         array_helper::array_to_array3_f32(res)
     };
-    let (bert_ori, phones, tones, lang_ids) = tts_util::parse_text(
+    let bert_predict = |token_ids: Vec<i64>, attention_masks: Vec<i64>| {
+        let bert_predict_fn = bert_predict_fn.clone();
+        Box::pin(async move {
+            let arr = array_helper::vec_to_array(vec![
+                array_helper::vec64_to_array64(token_ids).into(),
+                array_helper::vec64_to_array64(attention_masks).into(),
+            ]);
+            let res = bert_predict_fn
+                .apply(&js_sys::Object::new().into(), &arr)
+                .map_err(|e| {
+                    error::Error::OtherError(e.as_string().unwrap_or("unknown".to_string()))
+                })?;
+            let res = JsFuture::from(Into::<js_sys::Promise>::into(res))
+                .await
+                .map_err(|e| {
+                    sbv2_core::error::Error::OtherError(
+                        e.as_string().unwrap_or("unknown".to_string()),
+                    )
+                })?;
+            array_helper::array_to_array2_f32(res)
+        })
+            as std::pin::Pin<
+                Box<dyn std::future::Future<Output = error::Result<ndarray::Array2<f32>>>>,
+            >
+    };
+    let unit: tts_util::LengthUnit = sentence_length_unit.parse()?;
+    let sentences = tts_util::split_sentences(
         text,
-        &JTALK,
-        &tokenizer.tokenizer,
-        |token_ids: Vec<i64>, attention_masks: Vec<i64>| {
-            Box::pin(async move {
-                let arr = array_helper::vec_to_array(vec![
-                    array_helper::vec64_to_array64(token_ids).into(),
-                    array_helper::vec64_to_array64(attention_masks).into(),
-                ]);
-                let res = bert_predict_fn
-                    .apply(&js_sys::Object::new().into(), &arr)
-                    .map_err(|e| {
-                        error::Error::OtherError(e.as_string().unwrap_or("unknown".to_string()))
-                    })?;
-                let res = JsFuture::from(Into::<js_sys::Promise>::into(res))
-                    .await
-                    .map_err(|e| {
-                        sbv2_core::error::Error::OtherError(
-                            e.as_string().unwrap_or("unknown".to_string()),
-                        )
-                    })?;
-                array_helper::array_to_array2_f32(res)
-            })
-        },
-    )
-    .await?;
-    let audio = synthesize_wrap(
-        bert_ori.to_owned(),
-        phones,
-        tones,
-        lang_ids,
-        style::get_style_vector(&style_vectors.style_vector, style_id, style_weight)?,
-        sdp_ratio,
-        length_scale,
-    )
-    .await?;
-    Ok(array_helper::vec8_to_array8(tts_util::array_to_vec(audio)?))
+        max_sentence_length.unwrap_or(usize::MAX),
+        &tts_util::PauseOptions::default(),
+        |s| tts_util::sentence_length(s, unit, &JTALK, &tokenizer.tokenizer),
+    );
+    let style_vector =
+        style::get_style_vector(&style_vectors.style_vector, style_id, style_weight)?;
+    let mut samples = vec![];
+    for (i, sentence) in sentences.iter().enumerate() {
+        let (bert_ori, phones, tones, lang_ids) =
+            tts_util::parse_text(&sentence.text, &JTALK, &tokenizer.tokenizer, &bert_predict)
+                .await?;
+        let audio = synthesize_wrap(
+            bert_ori.to_owned(),
+            phones,
+            tones,
+            lang_ids,
+            style_vector.clone(),
+            sdp_ratio,
+            length_scale,
+        )
+        .await?;
+        samples.extend(tts_util::array_to_samples(audio));
+        if i != sentences.len() - 1 {
+            let pause = (sentence.pause.max(0.0) * tts_util::SAMPLE_RATE as f32) as usize;
+            samples.resize(samples.len() + pause, 0.0);
+        }
+    }
+    if samples.is_empty() {
+        return Err(JsError::new("No text to synthesize"));
+    }
+    Ok(ndarray::Array3::from_shape_vec(
+        (1, 1, samples.len()),
+        samples,
+    )?)
 }
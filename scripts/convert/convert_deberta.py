@@ -9,6 +9,7 @@ import os
 
 parser = ArgumentParser()
 parser.add_argument("--model", default="ku-nlp/deberta-v2-large-japanese-char-wwm")
+parser.add_argument("--batch", action="store_true")
 args = parser.parse_args()
 model_name = args.model
 
@@ -20,9 +21,10 @@ tokenizer.save("../../models/tokenizer.json")
 
 
 class ORTDeberta(nn.Module):
-    def __init__(self, model_name):
+    def __init__(self, model_name, batch):
         super(ORTDeberta, self).__init__()
         self.model = AutoModelForMaskedLM.from_pretrained(model_name)
+        self.batch = batch
 
     def forward(self, input_ids, token_type_ids, attention_mask):
         inputs = {
@@ -31,11 +33,13 @@ class ORTDeberta(nn.Module):
             "attention_mask": attention_mask,
         }
         res = self.model(**inputs, output_hidden_states=True)
-        res = torch.cat(res["hidden_states"][-3:-2], -1)[0].cpu()
-        return res
+        res = torch.cat(res["hidden_states"][-3:-2], -1)
+        if not self.batch:
+            res = res[0]
+        return res.cpu()
 
 
-model = ORTDeberta(model_name)
+model = ORTDeberta(model_name, args.batch)
 inputs = AutoTokenizer.from_pretrained(model_name)(
     "今日はいい天気ですね", return_tensors="pt"
 )
@@ -47,6 +51,14 @@ torch.onnx.export(
     input_names=["input_ids", "token_type_ids", "attention_mask"],
     output_names=["output"],
     verbose=True,
-    dynamic_axes={"input_ids": {1: "batch_size"}, "attention_mask": {1: "batch_size"}},
+    dynamic_axes=(
+        {
+            "input_ids": {0: "batch_size", 1: "seq_len"},
+            "attention_mask": {0: "batch_size", 1: "seq_len"},
+            "output": {0: "batch_size", 1: "seq_len"},
+        }
+        if args.batch
+        else {"input_ids": {1: "batch_size"}, "attention_mask": {1: "batch_size"}}
+    ),
 )
 os.system("onnxsim ../../models/deberta.onnx ../../models/deberta.onnx")
\ No newline at end of file
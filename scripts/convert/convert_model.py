@@ -23,6 +23,11 @@ parser = ArgumentParser()
 parser.add_argument("--style_file", required=True)
 parser.add_argument("--config_file", required=True)
 parser.add_argument("--model_file", required=True)
+parser.add_argument(
+    "--with_durations",
+    action="store_true",
+    help="export predicted phoneme durations as an additional output",
+)
 args = parser.parse_args()
 style_file = args.style_file
 config_file = args.config_file
@@ -41,6 +46,13 @@ with open(f"../../models/style_vectors_{out_name}.json", "w") as f:
         {
             "data": data,
             "shape": array.shape,
+            "names": [
+                name
+                for name, _ in sorted(
+                    hyper_parameters.data.style2id.items(), key=lambda x: x[1]
+                )
+            ],
+            "n_speakers": hyper_parameters.data.n_speakers,
         },
         f,
     )
@@ -95,7 +107,7 @@ model = get_net_g(
 
 
 def forward(x, x_len, sid, tone, lang, bert, style, length_scale, sdp_ratio, noise_scale, noise_scale_w):
-    return model.infer(
+    res = model.infer(
         x,
         x_len,
         sid,
@@ -108,6 +120,10 @@ def forward(x, x_len, sid, tone, lang, bert, style, length_scale, sdp_ratio, noi
         noise_scale=noise_scale,
         noise_scale_w=noise_scale_w,
     )
+    if args.with_durations:
+        # attn: [batch, 1, frames, x_tst] -> durations: [batch, 1, x_tst]
+        return res[0], res[1].sum(2)
+    return res
 
 
 model.forward = forward
@@ -151,7 +167,7 @@ torch.onnx.export(
         "noise_scale",
         "noise_scale_w"
     ],
-    output_names=["output"],
+    output_names=["output", "durations"] if args.with_durations else ["output"],
 )
 os.system(f"onnxsim ../../models/model_{out_name}.onnx ../../models/model_{out_name}.onnx")
 onnxfile = open(f"../../models/model_{out_name}.onnx", "rb").read()